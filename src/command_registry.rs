@@ -1,47 +1,127 @@
 use crate::definitions::{TexToken, TexTokenType};
+use crate::macro_packs::physics_macros;
+use crate::map::SYMBOL_MAP;
+use crate::tex_parser_utils::{
+    find_closing_match, LEFT_CURLY_BRACKET, LEFT_PARENTHESIS, LEFT_SQUARE_BRACKET, RIGHT_CURLY_BRACKET,
+    RIGHT_PARENTHESIS, RIGHT_SQUARE_BRACKET,
+};
 use crate::tex_tokenizer::tokenize;
 use std::collections::HashMap;
 
 pub const UNARY_COMMANDS: &[&'static str] = &[
     "text",
     "bar",
+    "bcancel",
     "bold",
     "boldsymbol",
+    "Bra",
+    "Braket",
+    "bm",
+    "boxed",
+    "cancel",
     "ddot",
     "dot",
+    "fbox",
     "hat",
+    "Ket",
+    "hphantom",
+    "clap",
+    "llap",
+    "rlap",
     "mathbb",
     "mathbf",
+    "mathbfit",
     "mathcal",
     "mathfrak",
     "mathit",
     "mathrm",
     "mathscr",
     "mathsf",
+    "mathsfit",
     "mathtt",
+    "mathclap",
+    "mathllap",
+    "mathrlap",
+    "mathbin",
+    "mathclose",
+    "mathinner",
+    "mathop",
+    "mathopen",
+    "mathord",
+    "mathpunct",
+    "mathrel",
+    "not",
+    "mod",
+    "pmod",
+    "pod",
     "operatorname",
+    "operatorname*",
     "overbrace",
     "overline",
+    "phantom",
     "pmb",
-    "rm",
     "tilde",
     "underbrace",
     "underline",
     "vec",
+    "vphantom",
     "overrightarrow",
     "widehat",
     "widetilde",
+    "xcancel",
     "floor", // This is a custom macro
 ];
 
-pub const BINARY_COMMANDS: &[&'static str] = &["frac", "tfrac", "binom", "dbinom", "dfrac", "tbinom", "overset"];
+pub const BINARY_COMMANDS: &[&'static str] = &[
+    "frac", "tfrac", "binom", "dbinom", "dfrac", "tbinom", "overset", "cancelto",
+];
+
+// Commands taking an xcolor color specification before their body, e.g. \textcolor[HTML]{FF0000}{x}
+pub const COLOR_COMMANDS: &[&str] = &["textcolor", "colorbox"];
+
+// Commands taking a TeX length, e.g. \hspace{1em} or \mkern-3mu
+pub const LENGTH_COMMANDS: &[&str] = &["hspace", "hskip", "kern", "mkern", "mskip", "mspace"];
+
+// Fractions with a layout of their own, \cfrac{a}{b} and \genfrac{(}{)}{0pt}{}{a}{b}
+pub const GENERALIZED_FRACTION_COMMANDS: &[&str] = &["cfrac", "genfrac"];
+
+// Delimiter sizes, which the physics macros accept before their arguments, e.g. \qty\Big(x)
+pub const SIZE_COMMANDS: &[&str] = &[
+    "\\big", "\\Big", "\\bigg", "\\Bigg", "\\bigl", "\\Bigl", "\\biggl", "\\Biggl",
+];
+
+// siunitx commands, whose numbers and units are read as raw text, e.g. \SI{9.81}{\meter\per\second\squared}
+// \qty is also a physics pack macro, which keeps \qty{3}{\meter} with two braced arguments a quantity
+pub const SIUNITX_COMMANDS: &[&str] = &["SI", "si", "qty", "unit", "num", "ang"];
+
+// mhchem commands, whose formulas are read as raw text, e.g. \ce{H2SO4 -> 2H+ + SO4^2-}
+pub const MHCHEM_COMMANDS: &[&str] = &["ce", "pu"];
+
+// Declarations apply to the rest of the enclosing group, e.g. {\displaystyle a + b} or {\rm d}x
+pub const DECLARATION_COMMANDS: &[&str] = &[
+    "displaystyle",
+    "textstyle",
+    "scriptstyle",
+    "scriptscriptstyle",
+    "bf",
+    "cal",
+    "it",
+    "rm",
+    "sf",
+    "tt",
+    "mathversion", // takes the version name as an argument, e.g. \mathversion{bold}
+    "color",       // takes a color as an argument, e.g. \color{red} or \color[rgb]{1,0,0}
+];
 
 pub const OPTION_UNARY_COMMANDS: &[&'static str] = &[];
 
-pub const OPTION_BINARY_COMMANDS: &[&'static str] = &["sqrt"];
+pub const OPTION_BINARY_COMMANDS: &[&'static str] = &["sqrt", "smash"];
 
 pub type ExpandResult = Result<(Vec<TexToken>, usize), String>;
 
+// Deeper nesting of macro expansions is taken as a macro that expands to itself
+const MAX_EXPANSION_DEPTH: usize = 64;
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum CommandType {
     Symbol,
@@ -49,6 +129,23 @@ pub enum CommandType {
     Binary,
     OptionalUnary,
     OptionalBinary,
+    // custom macros with three to nine braced arguments
+    Nary(usize),
+    // custom macros with three to nine arguments, the first of which is optional
+    OptionalNary(usize),
+    Declaration,
+    Color,
+    Length,
+    GeneralizedFraction,
+    Siunitx,
+    Mhchem,
+    // a leading [..] or (..) argument and braced arguments, at most the given number in total,
+    // passed to the implementation with their delimiters after a * or size command such as \Big,
+    // which is passed first on its own when present
+    Variadic(usize),
+    // a mathtools paired delimiter taking the given number of braced arguments, passed to the
+    // implementation after the * or [size] modifier, which is empty when absent
+    PairedDelimiter(usize),
 }
 
 pub struct CustomMacro {
@@ -90,6 +187,11 @@ impl CommandRegistry {
         }
     }
 
+    // \dv, \pdv, \abs, \norm, \bra, \ket, \qty and the other macros of the physics package
+    pub fn register_physics_macros(&mut self) {
+        self.register_custom_macros(physics_macros());
+    }
+
     pub fn get_command_type(&self, command_name: &str) -> Option<CommandType> {
         if UNARY_COMMANDS.contains(&command_name) {
             Some(CommandType::Unary)
@@ -97,6 +199,18 @@ impl CommandRegistry {
             Some(CommandType::Binary)
         } else if OPTION_BINARY_COMMANDS.contains(&command_name) {
             Some(CommandType::OptionalBinary)
+        } else if LENGTH_COMMANDS.contains(&command_name) {
+            Some(CommandType::Length)
+        } else if COLOR_COMMANDS.contains(&command_name) {
+            Some(CommandType::Color)
+        } else if DECLARATION_COMMANDS.contains(&command_name) {
+            Some(CommandType::Declaration)
+        } else if GENERALIZED_FRACTION_COMMANDS.contains(&command_name) {
+            Some(CommandType::GeneralizedFraction)
+        } else if SIUNITX_COMMANDS.contains(&command_name) {
+            Some(CommandType::Siunitx)
+        } else if MHCHEM_COMMANDS.contains(&command_name) {
+            Some(CommandType::Mhchem)
         } else if self.custom_macro_names.contains_key(command_name) {
             self.custom_macro_names.get(command_name).copied()
         } else {
@@ -106,6 +220,14 @@ impl CommandRegistry {
     }
 
     pub fn expand_macros(&self, tokens: &[TexToken]) -> Result<Vec<TexToken>, String> {
+        self.expand_macros_at_depth(tokens, 0)
+    }
+
+    // the expansion of a macro is expanded again, so that macros can be defined in terms of others
+    fn expand_macros_at_depth(&self, tokens: &[TexToken], depth: usize) -> Result<Vec<TexToken>, String> {
+        if depth > MAX_EXPANSION_DEPTH {
+            return Err("Macro expansion is too deep, is a macro defined in terms of itself?".to_string());
+        }
         let mut expanded_tokens: Vec<TexToken> = Vec::new();
         let mut pos: usize = 0;
 
@@ -114,7 +236,7 @@ impl CommandRegistry {
             if token.token_type == TexTokenType::Command {
                 if let Some(custom_macro) = self.custom_macros.iter().find(|macro_| macro_.name == token.value) {
                     let (expanded_command, new_pos) = self.expand_command(tokens, custom_macro, pos)?;
-                    expanded_tokens.extend(expanded_command);
+                    expanded_tokens.extend(self.expand_macros_at_depth(&expanded_command, depth + 1)?);
                     pos = new_pos;
                 } else {
                     expanded_tokens.push(token.clone());
@@ -141,44 +263,26 @@ impl CommandRegistry {
                 // no arguments, don't move the pos
             }
             CommandType::Unary => {
-                if !tokens[pos].value.eq("{") {
-                    return Err(format!("Expecting one argument for command {}", command_name));
-                }
-                pos += 1;
-                if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_token(tokens, pos) {
-                    let argument: &[TexToken] = &tokens[pos..right_curly_bracket_pos];
-                    arguments.push(self.expand_macros(argument)?);
-                    pos = right_curly_bracket_pos + 1;
-                } else {
-                    return Err(format!("Unmatched curly brackets for command {}", command_name));
-                }
+                pos = self.collect_arguments(tokens, pos, 1, command_name, &mut arguments)?;
             }
             CommandType::Binary => {
-                if !tokens[pos].value.eq("{") {
-                    return Err(format!("No argument provided for command {}", command_name));
-                }
-                pos += 1;
-                if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_token(tokens, pos) {
-                    let first_argument: &[TexToken] = &tokens[pos..right_curly_bracket_pos];
-                    arguments.push(self.expand_macros(first_argument)?);
-                    pos = right_curly_bracket_pos;
-                } else {
-                    return Err(format!("Unmatched curly brackets for command {}", command_name));
-                }
-                pos += 1;
-
-                if !tokens[pos].value.eq("{") {
-                    return Err(format!("Expecting two arguments for command {}", command_name));
-                }
-                pos += 1;
-                if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_token(tokens, pos) {
-                    let second_argument: &[TexToken] = &tokens[pos..right_curly_bracket_pos];
-                    arguments.push(self.expand_macros(second_argument)?);
-                    pos = right_curly_bracket_pos;
-                } else {
-                    return Err(format!("Unmatched curly brackets for command {}", command_name));
+                pos = self.collect_arguments(tokens, pos, 2, command_name, &mut arguments)?;
+            }
+            CommandType::Nary(num_of_args) => {
+                pos = self.collect_arguments(tokens, pos, num_of_args, command_name, &mut arguments)?;
+            }
+            CommandType::OptionalNary(num_of_args) => {
+                if tokens.get(pos).is_some_and(|token| token.eq(&LEFT_SQUARE_BRACKET)) {
+                    pos += 1;
+                    if let Some(right_square_bracket) = find_matching_right_square_bracket_token(tokens, pos) {
+                        arguments.push(self.expand_macros(&tokens[pos..right_square_bracket])?);
+                        pos = right_square_bracket + 1;
+                    } else {
+                        return Err(format!("Unmatched square brackets for command {}", command_name));
+                    }
                 }
-                pos += 1;
+                let num_of_mandatory_args = num_of_args - 1;
+                pos = self.collect_arguments(tokens, pos, num_of_mandatory_args, command_name, &mut arguments)?;
             }
             CommandType::OptionalUnary => {
                 let s;
@@ -264,16 +368,114 @@ impl CommandRegistry {
                     }
                 };
             }
+            CommandType::Variadic(max_args) => {
+                // \abs*{x} or \qty\Big(x)
+                let num_of_modifiers = match tokens.get(pos) {
+                    Some(token) if token.value == "*" || SIZE_COMMANDS.contains(&token.value.as_str()) => {
+                        arguments.push(vec![token.clone()]);
+                        pos += 1;
+                        1
+                    }
+                    _ => 0,
+                };
+                while arguments.len() < max_args + num_of_modifiers && pos < tokens.len() {
+                    let is_first = arguments.len() == num_of_modifiers;
+                    let closing = if tokens[pos].eq(&LEFT_CURLY_BRACKET) {
+                        find_closing_match(tokens, pos, &LEFT_CURLY_BRACKET, &RIGHT_CURLY_BRACKET)
+                    } else if is_first && tokens[pos].eq(&LEFT_SQUARE_BRACKET) {
+                        find_closing_match(tokens, pos, &LEFT_SQUARE_BRACKET, &RIGHT_SQUARE_BRACKET)
+                    } else if is_first && tokens[pos].eq(&LEFT_PARENTHESIS) {
+                        find_closing_match(tokens, pos, &LEFT_PARENTHESIS, &RIGHT_PARENTHESIS)
+                    } else {
+                        break;
+                    };
+                    if closing == -1 {
+                        return Err(format!("Unmatched {} for command {}", tokens[pos].value, command_name));
+                    }
+                    let closing = closing as usize;
+                    let mut argument = vec![tokens[pos].clone()];
+                    argument.extend(self.expand_macros(&tokens[pos + 1..closing])?);
+                    argument.push(tokens[closing].clone());
+                    arguments.push(argument);
+                    pos = closing + 1;
+                }
+            }
+            CommandType::PairedDelimiter(num_of_args) => {
+                // \abs*{x} or \abs[\big]{x}
+                let mut modifier = Vec::new();
+                if tokens.get(pos).is_some_and(|token| token.value == "*") {
+                    modifier.push(tokens[pos].clone());
+                    pos += 1;
+                } else if tokens.get(pos).is_some_and(|token| token.eq(&LEFT_SQUARE_BRACKET)) {
+                    let closing = find_closing_match(tokens, pos, &LEFT_SQUARE_BRACKET, &RIGHT_SQUARE_BRACKET);
+                    if closing == -1 {
+                        return Err(format!("Unmatched square brackets for command {}", command_name));
+                    }
+                    modifier.extend_from_slice(&tokens[pos + 1..closing as usize]);
+                    pos = closing as usize + 1;
+                }
+                arguments.push(modifier);
+                for _ in 0..num_of_args {
+                    if !tokens.get(pos).is_some_and(|token| token.eq(&LEFT_CURLY_BRACKET)) {
+                        return Err(format!(
+                            "Expecting {} arguments for command {}",
+                            num_of_args, command_name
+                        ));
+                    }
+                    let closing = find_closing_match(tokens, pos, &LEFT_CURLY_BRACKET, &RIGHT_CURLY_BRACKET);
+                    if closing == -1 {
+                        return Err(format!("Unmatched curly brackets for command {}", command_name));
+                    }
+                    arguments.push(self.expand_macros(&tokens[pos + 1..closing as usize])?);
+                    pos = closing as usize + 1;
+                }
+            }
+            CommandType::Declaration
+            | CommandType::Color
+            | CommandType::Length
+            | CommandType::GeneralizedFraction
+            | CommandType::Siunitx
+            | CommandType::Mhchem => {
+                return Err(format!("{} cannot be a custom macro", command_name));
+            }
         }
 
         let expanded_tokens = (custom_macro.implementation)(&arguments)?;
         Ok((expanded_tokens, pos))
     }
+
+    // {a}{b}... -> the given number of expanded arguments, returns the position after the last one
+    fn collect_arguments(
+        &self,
+        tokens: &[TexToken],
+        start: usize,
+        num_of_args: usize,
+        command_name: &str,
+        arguments: &mut Vec<Vec<TexToken>>,
+    ) -> Result<usize, String> {
+        let mut pos = start;
+        for _ in 0..num_of_args {
+            if !tokens.get(pos).is_some_and(|token| token.eq(&LEFT_CURLY_BRACKET)) {
+                return Err(format!(
+                    "Expecting {} arguments for command {}",
+                    num_of_args, command_name
+                ));
+            }
+            pos += 1;
+            if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_token(tokens, pos) {
+                arguments.push(self.expand_macros(&tokens[pos..right_curly_bracket_pos])?);
+                pos = right_curly_bracket_pos + 1;
+            } else {
+                return Err(format!("Unmatched curly brackets for command {}", command_name));
+            }
+        }
+        Ok(pos)
+    }
 }
 
 fn find_matching_right_curly_bracket_token(tokens: &[TexToken], start: usize) -> Option<usize> {
     let mut count = 1;
-    let mut pos = start + 1;
+    let mut pos = start;
 
     while count > 0 {
         if pos >= tokens.len() {
@@ -317,15 +519,15 @@ fn find_matching_right_square_bracket_token(tokens: &[TexToken], start: usize) -
     Some(pos - 1)
 }
 
-fn find_matching_right_curly_bracket_char(latex: &Vec<char>, start: usize) -> Option<usize> {
+fn find_matching_right_curly_bracket_char(latex: &[char], start: usize) -> Option<usize> {
     let mut count = 1;
-    let mut pos = start + 1;
+    let mut pos = start;
 
     while count > 0 {
         if pos >= latex.len() {
             return None;
         }
-        if pos + 1 < latex.len() && latex[pos] == '\\' && latex[pos + 1] == '}' {
+        if pos + 1 < latex.len() && latex[pos] == '\\' && (latex[pos + 1] == '{' || latex[pos + 1] == '}') {
             pos += 2;
             continue;
         }
@@ -340,7 +542,7 @@ fn find_matching_right_curly_bracket_char(latex: &Vec<char>, start: usize) -> Op
     Some(pos - 1)
 }
 
-fn find_matching_right_square_bracket_char(latex: &Vec<char>, start: usize) -> Option<usize> {
+fn find_matching_right_square_bracket_char(latex: &[char], start: usize) -> Option<usize> {
     let mut count = 1;
     let mut pos = start;
 
@@ -363,98 +565,372 @@ fn find_matching_right_square_bracket_char(latex: &Vec<char>, start: usize) -> O
     Some(pos - 1)
 }
 
+// How a definition treats an earlier macro of the same name
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DefinitionMode {
+    // \newcommand fails if the macro exists
+    New,
+    // \renewcommand, \def, \let and the declarations replace it
+    Renew,
+    // \providecommand keeps it, and also keeps built-in commands
+    Provide,
+}
+
+// The arguments construct_custom_macro was called with: arity, default value and definition
+type MacroDefinition = (usize, Option<String>, String);
+
+#[derive(Default)]
+struct MacroDefinitions {
+    custom_macros: Vec<CustomMacro>,
+    // kept so that \let can copy a macro defined before
+    definitions: HashMap<String, MacroDefinition>,
+}
+
+impl MacroDefinitions {
+    fn define(
+        &mut self,
+        custom_macro: CustomMacro,
+        definition: Option<MacroDefinition>,
+        mode: DefinitionMode,
+    ) -> Result<(), String> {
+        let name = custom_macro.name.clone();
+        let existing = self.custom_macros.iter().position(|macro_| macro_.name == name);
+        match (mode, existing) {
+            (DefinitionMode::New, Some(_)) => return Err(format!("{} is already defined", name)),
+            (DefinitionMode::Provide, Some(_)) => return Ok(()),
+            (DefinitionMode::Provide, None) if is_builtin_command(&name) => return Ok(()),
+            (_, Some(index)) => self.custom_macros[index] = custom_macro,
+            (_, None) => self.custom_macros.push(custom_macro),
+        }
+        match definition {
+            Some(definition) => self.definitions.insert(name, definition),
+            None => self.definitions.remove(&name),
+        };
+        Ok(())
+    }
+
+    fn define_macro(&mut self, name: String, definition: MacroDefinition, mode: DefinitionMode) -> Result<(), String> {
+        let (num_of_args, default_value, body) = definition.clone();
+        let custom_macro = construct_custom_macro(name, num_of_args, default_value, body)?;
+        self.define(custom_macro, Some(definition), mode)
+    }
+}
+
+// Commands that the converter handles without any custom macro, e.g. \alpha or \frac
+fn is_builtin_command(name: &str) -> bool {
+    let name = name.trim_start_matches('\\');
+    let command_lists = [
+        UNARY_COMMANDS,
+        BINARY_COMMANDS,
+        OPTION_BINARY_COMMANDS,
+        LENGTH_COMMANDS,
+        COLOR_COMMANDS,
+        DECLARATION_COMMANDS,
+        GENERALIZED_FRACTION_COMMANDS,
+        SIUNITX_COMMANDS,
+        MHCHEM_COMMANDS,
+    ];
+    // \bmod is no plain symbol and is built by the converter
+    SYMBOL_MAP.contains_key(name) || name == "bmod" || command_lists.iter().any(|commands| commands.contains(&name))
+}
+
+fn skip_whitespaces(latex: &[char], start: usize) -> usize {
+    start + latex[start..].iter().take_while(|c| c.is_whitespace()).count()
+}
+
+// {\name} or \name -> \name and the position after it
+fn read_command_name(latex: &[char], start: usize) -> Result<(String, usize), String> {
+    let pos = skip_whitespaces(latex, start);
+    if latex.get(pos) == Some(&'{') {
+        let (name, new_pos) = read_braced_group(latex, pos)?;
+        let name = name.trim().to_string();
+        if !name.starts_with('\\') {
+            return Err("Expecting backslash for command name after {".to_string());
+        }
+        return Ok((name, new_pos));
+    }
+    if latex.get(pos) != Some(&'\\') {
+        return Err("Expecting a command name".to_string());
+    }
+    let letters = latex[pos + 1..].iter().take_while(|c| c.is_ascii_alphabetic()).count();
+    // control symbols such as \| have a single character name
+    let name_len = letters.max(1).min(latex.len() - pos - 1);
+    if name_len == 0 {
+        return Err("Expecting a command name".to_string());
+    }
+    Ok((latex[pos..pos + 1 + name_len].iter().collect(), pos + 1 + name_len))
+}
+
+// The content of the square bracket group starting at start, if there is one, and the position after it
+fn read_square_bracket_group(latex: &[char], start: usize) -> Result<(Option<String>, usize), String> {
+    let pos = skip_whitespaces(latex, start);
+    if latex.get(pos) != Some(&'[') {
+        return Ok((None, start));
+    }
+    let right_square_bracket =
+        find_matching_right_square_bracket_char(latex, pos + 1).ok_or("Unmatched square brackets".to_string())?;
+    Ok((
+        Some(latex[pos + 1..right_square_bracket].iter().collect()),
+        right_square_bracket + 1,
+    ))
+}
+
+fn parse_num_of_args(num_of_args: Option<String>) -> Result<usize, String> {
+    num_of_args.map_or(Ok(0), |num_of_args| {
+        num_of_args.trim().parse::<usize>().map_err(|e| e.to_string())
+    })
+}
+
 pub fn parse_custom_macros(latex: &str) -> Result<Vec<CustomMacro>, String> {
     let latex: Vec<char> = latex.chars().collect();
-    let pattern: Vec<char> = "\\newcommand".chars().collect();
-    let pattern_len = pattern.len();
     let mut pos = 0;
-    let mut custom_macros: Vec<CustomMacro> = Vec::new();
-
-    while pos < latex.len().saturating_sub(pattern_len) {
-        if latex[pos..pos + pattern_len] == pattern[..] {
-            pos += pattern_len;
-            // extract the new command name
-            let new_command_name: String;
-            if latex.get(pos) != Some(&'{') {
-                return Err("Expecting { after \\newcommand".to_string());
-            }
-            pos += 1;
-            if latex.get(pos) != Some(&'\\') {
-                return Err("Expecting backslash for command name after {".to_string());
-            }
-            if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_char(&latex, pos) {
-                new_command_name = latex[pos..right_curly_bracket_pos].iter().collect();
-                pos = right_curly_bracket_pos;
-            } else {
-                return Err("Unmatched curly brackets".to_string());
-            }
+    let mut macros = MacroDefinitions::default();
 
-            // check if there is a specification of number of arguments
-            let num_of_args: usize;
+    while pos < latex.len() {
+        if latex[pos] != '\\' {
             pos += 1;
-            if latex.get(pos) == Some(&'[') {
-                pos += 1;
-                if let Some(right_square_bracket) = find_matching_right_square_bracket_char(&latex, pos) {
-                    num_of_args = latex[pos..right_square_bracket]
-                        .iter()
-                        .collect::<String>()
-                        .parse::<usize>()
-                        .map_err(|e| e.to_string())?;
-                    if num_of_args > 2 {
-                        return Err("Only unary and binary commands are supported".to_string());
+            continue;
+        }
+        let command: String = latex[pos + 1..]
+            .iter()
+            .take_while(|c| c.is_ascii_alphabetic())
+            .collect();
+        pos += 1 + command.len();
+        match command.as_str() {
+            "newcommand" | "renewcommand" | "providecommand" => {
+                let mode = match command.as_str() {
+                    "newcommand" => DefinitionMode::New,
+                    "renewcommand" => DefinitionMode::Renew,
+                    _ => DefinitionMode::Provide,
+                };
+                // the starred forms only forbid paragraphs in the arguments
+                if latex.get(pos) == Some(&'*') {
+                    pos += 1;
+                }
+                let (new_command_name, new_pos) = read_command_name(&latex, pos)?;
+                // the number of arguments, then the default value of the first one
+                let (num_of_args, new_pos) = read_square_bracket_group(&latex, new_pos)?;
+                let (default_value, new_pos) = read_square_bracket_group(&latex, new_pos)?;
+                let (definition, new_pos) = read_braced_group(&latex, skip_whitespaces(&latex, new_pos))?;
+                pos = new_pos;
+                macros.define_macro(
+                    new_command_name,
+                    (parse_num_of_args(num_of_args)?, default_value, definition),
+                    mode,
+                )?;
+            }
+            "def" => {
+                let (new_command_name, new_pos) = read_command_name(&latex, pos)?;
+                pos = new_pos;
+                // the parameter text #1#2...
+                let mut num_of_args = 0;
+                while latex.get(pos) == Some(&'#') {
+                    match latex.get(pos + 1).and_then(|c| c.to_digit(10)) {
+                        Some(index) if index as usize == num_of_args + 1 => {
+                            num_of_args += 1;
+                            pos += 2;
+                        }
+                        Some(_) => {
+                            return Err(format!(
+                                "Expecting #{} in the parameters of {}",
+                                num_of_args + 1,
+                                new_command_name
+                            ))
+                        }
+                        None => break,
                     }
-                    pos = right_square_bracket;
-                } else {
-                    return Err("Unmatched square brackets".to_string());
                 }
-                pos += 1;
-            } else {
-                num_of_args = 0;
+                // delimited parameters such as \def\foo#1.{...} are not supported, the definition is skipped
+                let delimited = latex.get(pos) != Some(&'{');
+                if delimited {
+                    pos += latex[pos..]
+                        .iter()
+                        .position(|c| *c == '{')
+                        .ok_or(format!("Missing definition of {}", new_command_name))?;
+                }
+                let (definition, new_pos) = read_braced_group(&latex, pos)?;
+                pos = new_pos;
+                if !delimited {
+                    macros.define_macro(new_command_name, (num_of_args, None, definition), DefinitionMode::Renew)?;
+                }
             }
-
-            // check if there is a default value for the first argument
-            let default_value: Option<String>;
-            if latex.get(pos) == Some(&'[') {
-                pos += 1;
-                if let Some(right_square_bracket) = find_matching_right_square_bracket_char(&latex, pos) {
-                    default_value = Some(latex[pos..right_square_bracket].iter().collect::<String>());
-                    pos = right_square_bracket;
-                } else {
-                    return Err("Unmatched square brackets".to_string());
+            "let" => {
+                let (new_command_name, new_pos) = read_command_name(&latex, pos)?;
+                pos = skip_whitespaces(&latex, new_pos);
+                if latex.get(pos) == Some(&'=') {
+                    pos += 1;
+                }
+                let (target, new_pos) = read_command_name(&latex, pos)?;
+                pos = new_pos;
+                // \let copies the current meaning of a custom macro, anything else is aliased
+                let definition = macros
+                    .definitions
+                    .get(&target)
+                    .cloned()
+                    .unwrap_or((0, None, target.clone()));
+                if target != new_command_name {
+                    macros.define_macro(new_command_name, definition, DefinitionMode::Renew)?;
                 }
-                pos += 1;
-            } else {
-                default_value = None;
             }
-
-            // extract the definition
-            let definition: String;
-            if latex.get(pos) != Some(&'{') {
-                return Err("Expecting { before the definition".to_string());
+            "DeclareMathOperator" => {
+                // the starred version places limits above and below in display style
+                let operator_command = if latex.get(pos) == Some(&'*') {
+                    pos += 1;
+                    "\\operatorname*"
+                } else {
+                    "\\operatorname"
+                };
+                let (new_command_name, new_pos) = read_command_name(&latex, pos)?;
+                let (operator_text, new_pos) = read_braced_group(&latex, skip_whitespaces(&latex, new_pos))?;
+                pos = new_pos;
+                macros.define_macro(
+                    new_command_name,
+                    (0, None, format!("{}{{{}}}", operator_command, operator_text)),
+                    DefinitionMode::Renew,
+                )?;
             }
-            pos += 1;
-            if let Some(right_curly_bracket_pos) = find_matching_right_curly_bracket_char(&latex, pos) {
-                definition = latex[pos..right_curly_bracket_pos].iter().collect();
-                pos = right_curly_bracket_pos;
-            } else {
-                return Err("Unmatched curly brackets".to_string());
+            "DeclarePairedDelimiter" | "DeclarePairedDelimiterX" => {
+                // the X variant takes arguments and a body, e.g. \DeclarePairedDelimiterX{\set}[1]{\{}{\}}{#1}
+                let is_x = command == "DeclarePairedDelimiterX";
+                let (new_command_name, new_pos) = read_command_name(&latex, pos)?;
+                pos = new_pos;
+                let mut num_of_args = 1;
+                if is_x {
+                    let (num, new_pos) = read_square_bracket_group(&latex, pos)?;
+                    num_of_args = parse_num_of_args(num)?;
+                    pos = new_pos;
+                }
+                let (left, new_pos) = read_braced_group(&latex, skip_whitespaces(&latex, pos))?;
+                let (right, new_pos) = read_braced_group(&latex, skip_whitespaces(&latex, new_pos))?;
+                pos = new_pos;
+                let body = if is_x {
+                    let (body, new_pos) = read_braced_group(&latex, skip_whitespaces(&latex, pos))?;
+                    pos = new_pos;
+                    body
+                } else {
+                    "#1".to_string()
+                };
+                let custom_macro = construct_paired_delimiter(new_command_name, num_of_args, left, right, body);
+                macros.define(custom_macro, None, DefinitionMode::Renew)?;
+            }
+            _ => {
+                // a control symbol such as \{ or \\
+                if command.is_empty() {
+                    pos += 1;
+                }
             }
-
-            custom_macros.push(construct_custom_macro(
-                new_command_name,
-                num_of_args,
-                default_value,
-                definition,
-            )?);
         }
-        pos += 1;
     }
 
-    if custom_macros.is_empty() && latex.len() > 0 {
+    if macros.custom_macros.is_empty() && !latex.is_empty() {
         return Err("No custom macros found".to_string());
     }
 
-    Ok(custom_macros)
+    Ok(macros.custom_macros)
+}
+
+// The content of the braced group starting at start and the position after it
+fn read_braced_group(latex: &[char], start: usize) -> Result<(String, usize), String> {
+    if latex.get(start) != Some(&'{') {
+        return Err("Expecting {".to_string());
+    }
+    let right_curly_bracket_pos =
+        find_matching_right_curly_bracket_char(latex, start + 1).ok_or("Unmatched curly brackets".to_string())?;
+    Ok((
+        latex[start + 1..right_curly_bracket_pos].iter().collect(),
+        right_curly_bracket_pos + 1,
+    ))
+}
+
+// \abs{x} -> \lvert x \rvert, \abs*{x} -> \left\lvert x \right\rvert, where \delimsize in the body of
+// the X variant becomes \middle in the starred form. The size of the [\big] form is not supported and ignored.
+fn construct_paired_delimiter(
+    new_command_name: String,
+    num_of_args: usize,
+    left: String,
+    right: String,
+    body: String,
+) -> CustomMacro {
+    let implementation = move |args: &Vec<Vec<TexToken>>| {
+        let starred = args[0].first().is_some_and(|token| token.value == "*");
+        let (body, left, right) = if starred {
+            (
+                body.replace("\\delimsize", "\\middle"),
+                format!("\\left{}", left),
+                format!("\\right{}", right),
+            )
+        } else {
+            (body.replace("\\delimsize", ""), left.clone(), right.clone())
+        };
+        // the delimiters are tokenized apart so that \lvert cannot run into a body starting with letters
+        let mut tokens = tokenize(&left)?;
+        tokens.push(TexToken::new(TexTokenType::Space, " ".to_string()));
+        tokens.extend(tokenize(&substitute_arguments(&body, &args[1..])?)?);
+        tokens.push(TexToken::new(TexTokenType::Space, " ".to_string()));
+        tokens.extend(tokenize(&right)?);
+        Ok(tokens)
+    };
+    CustomMacro {
+        name: new_command_name,
+        command_type: CommandType::PairedDelimiter(num_of_args),
+        implementation: Box::new(implementation),
+    }
+}
+
+// Whether the text ends with a control word such as \alpha, which letters appended to it would extend
+fn ends_with_control_word(text: &str) -> bool {
+    let name_len = text.chars().rev().take_while(|c| c.is_ascii_alphabetic()).count();
+    name_len > 0 && text[..text.len() - name_len].ends_with('\\')
+}
+
+// The text of a token list, with a space after a control word followed by a letter, e.g. \alpha x
+fn join_tokens(tokens: &[TexToken]) -> String {
+    let mut res = String::new();
+    for token in tokens {
+        if token.value.starts_with(|c: char| c.is_ascii_alphabetic()) && ends_with_control_word(&res) {
+            res.push(' ');
+        }
+        res.push_str(&token.value);
+    }
+    res
+}
+
+// Replace #1 to #9 in the definition with the arguments and ## with #
+fn substitute_arguments(definition: &str, args: &[Vec<TexToken>]) -> Result<String, String> {
+    let chars: Vec<char> = definition.chars().collect();
+    let mut res = String::new();
+    let mut pos = 0;
+    let mut after_argument = false;
+    while pos < chars.len() {
+        let (piece, is_argument) = match (chars[pos], chars.get(pos + 1)) {
+            ('#', Some('#')) => {
+                pos += 2;
+                ("#".to_string(), false)
+            }
+            ('#', Some(digit)) if digit.is_ascii_digit() => {
+                let index = digit.to_digit(10).unwrap() as usize;
+                if index == 0 || index > args.len() {
+                    return Err(format!("Illegal parameter number #{} in the definition", index));
+                }
+                pos += 2;
+                (join_tokens(&args[index - 1]), true)
+            }
+            (c, _) => {
+                pos += 1;
+                (c.to_string(), false)
+            }
+        };
+        // keep \alpha from running into the letters on the other side of an argument, e.g. \alpha#1 with x
+        if (is_argument || after_argument)
+            && piece.starts_with(|c: char| c.is_ascii_alphabetic())
+            && ends_with_control_word(&res)
+        {
+            res.push(' ');
+        }
+        res.push_str(&piece);
+        after_argument = is_argument;
+    }
+    Ok(res)
 }
 
 fn construct_custom_macro(
@@ -463,106 +939,42 @@ fn construct_custom_macro(
     default_value: Option<String>,
     definition: String,
 ) -> Result<CustomMacro, String> {
-    let command_type: CommandType;
-    let implementation: Box<dyn Fn(&Vec<Vec<TexToken>>) -> Result<Vec<TexToken>, String>>;
-
-    if let Some(default_value) = default_value {
-        // default value provided, so it's an optional unary or optional binary command
-        match num_of_args {
-            0 => {
-                return Err("Default value provided for a command with no arguments".to_string());
-            }
-            1 => {
-                // optional unary command
-                command_type = CommandType::OptionalUnary;
-                implementation = Box::new(move |args: &Vec<Vec<TexToken>>| {
-                    let replaced_string: String;
-                    if args.is_empty() {
-                        replaced_string = definition.replace("#1", &default_value);
-                    } else {
-                        replaced_string = definition.replace(
-                            "#1",
-                            &args[0].iter().map(|token| token.value.clone()).collect::<String>(),
-                        );
-                    }
-                    tokenize(&replaced_string)
-                });
-            }
-            2 => {
-                // optional binary command
-                command_type = CommandType::OptionalBinary;
-                implementation = Box::new(move |args: &Vec<Vec<TexToken>>| {
-                    let replaced_string: String;
-                    if args.len() == 1 {
-                        replaced_string = definition.replace("#1", &default_value).replace(
-                            "#2",
-                            &args[0].iter().map(|token| token.value.clone()).collect::<String>(),
-                        );
-                    } else if args.len() == 2 {
-                        replaced_string = definition
-                            .replace(
-                                "#1",
-                                &args[0].iter().map(|token| token.value.clone()).collect::<String>(),
-                            )
-                            .replace(
-                                "#2",
-                                &args[1].iter().map(|token| token.value.clone()).collect::<String>(),
-                            );
-                    } else {
-                        return Err("Expecting one or two arguments".to_string());
-                    }
-                    tokenize(&replaced_string)
-                });
-            }
-            _ => {
-                return Err("Only unary and binary commands are supported".to_string());
-            }
+    if num_of_args > 9 {
+        return Err("Commands take at most nine arguments".to_string());
+    }
+    // report parameters beyond the arity when defining rather than when expanding
+    substitute_arguments(&definition, &vec![Vec::new(); num_of_args])?;
+    // with a default value, the first argument is optional
+    let command_type = match (num_of_args, default_value.is_some()) {
+        (0, true) => {
+            return Err("Default value provided for a command with no arguments".to_string());
         }
-    } else {
-        // no default value, it's either a symbol, unary or binary command
-        match num_of_args {
-            0 => {
-                // symbol command
-                command_type = CommandType::Symbol;
-                implementation = Box::new(move |_| tokenize(&definition));
-            }
-            1 => {
-                // unary command
-                command_type = CommandType::Unary;
-                implementation = Box::new(move |args: &Vec<Vec<TexToken>>| {
-                    let replaced_string = definition.replace(
-                        "#1",
-                        &args[0].iter().map(|token| token.value.clone()).collect::<String>(),
-                    );
-                    tokenize(&replaced_string)
-                });
-            }
-            2 => {
-                // binary command
-                command_type = CommandType::Binary;
-                implementation = Box::new(move |args: &Vec<Vec<TexToken>>| {
-                    let replaced_string = definition
-                        .replace(
-                            "#1",
-                            &args[0].iter().map(|token| token.value.clone()).collect::<String>(),
-                        )
-                        .replace(
-                            "#2",
-                            &args[1].iter().map(|token| token.value.clone()).collect::<String>(),
-                        );
-                    tokenize(&replaced_string)
-                });
-            }
-            _ => {
-                return Err("Only unary and binary commands are supported".to_string());
+        (0, false) => CommandType::Symbol,
+        (1, false) => CommandType::Unary,
+        (2, false) => CommandType::Binary,
+        (n, false) => CommandType::Nary(n),
+        (1, true) => CommandType::OptionalUnary,
+        (2, true) => CommandType::OptionalBinary,
+        (n, true) => CommandType::OptionalNary(n),
+    };
+
+    let implementation = move |args: &Vec<Vec<TexToken>>| {
+        let mut arguments = args.clone();
+        if let Some(default_value) = &default_value {
+            if arguments.len() < num_of_args {
+                arguments.insert(0, tokenize(default_value)?);
             }
         }
-    }
+        if arguments.len() != num_of_args {
+            return Err(format!("Expecting {} arguments", num_of_args));
+        }
+        tokenize(&substitute_arguments(&definition, &arguments)?)
+    };
 
     Ok(CustomMacro {
         name: new_command_name,
         command_type,
-        implementation,
+        implementation: Box::new(implementation),
     })
 }
 
@@ -613,6 +1025,58 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_command_registry_variadic() {
+        let mut registry = CommandRegistry::new();
+
+        // the arguments keep their delimiters, the count tells the forms apart
+        let implementation = |args: &Vec<Vec<TexToken>>| {
+            let mut res = tokenize(&format!(r"\expanded{}", args.len())).unwrap();
+            for arg in args {
+                res.extend(arg.iter().cloned());
+            }
+            Ok(res)
+        };
+        registry.register_custom_macro(r"\mycommand", CommandType::Variadic(2), Box::new(implementation));
+
+        let tokens = tokenize(r"\mycommand[n]{a}{b}{c}").unwrap();
+        let expanded_tokens = registry.expand_macros(&tokens).unwrap();
+        assert_eq!(expanded_tokens, tokenize(r"\expanded2[n]{a}{b}{c}").unwrap());
+
+        let tokens = tokenize(r"\mycommand(a) {b}").unwrap();
+        let expanded_tokens = registry.expand_macros(&tokens).unwrap();
+        assert_eq!(expanded_tokens, tokenize(r"\expanded1(a) {b}").unwrap());
+    }
+
+    #[test]
+    fn test_construct_custom_macro_nary() {
+        let custom_macro =
+            construct_custom_macro(r"\mycommand".to_string(), 3, None, "#3#2#1 ## #1".to_string()).unwrap();
+        assert_eq!(custom_macro.command_type, CommandType::Nary(3));
+        let args = vec![
+            tokenize("a").unwrap(),
+            tokenize(r"\beta").unwrap(),
+            tokenize("c").unwrap(),
+        ];
+        assert_eq!(
+            (custom_macro.implementation)(&args).unwrap(),
+            tokenize(r"c\beta a # a").unwrap()
+        );
+
+        let custom_macro = construct_custom_macro(
+            r"\mycommand".to_string(),
+            3,
+            Some("x".to_string()),
+            "#1#2#3".to_string(),
+        )
+        .unwrap();
+        assert_eq!(custom_macro.command_type, CommandType::OptionalNary(3));
+        let args = vec![tokenize("b").unwrap(), tokenize("c").unwrap()];
+        assert_eq!((custom_macro.implementation)(&args).unwrap(), tokenize("xbc").unwrap());
+
+        assert!(construct_custom_macro(r"\mycommand".to_string(), 10, None, String::new()).is_err());
+    }
+
     #[test]
     fn test_command_registry_simple_unary() {
         let mut registry = CommandRegistry::new();
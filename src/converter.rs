@@ -1,14 +1,666 @@
-use crate::definitions::{TexNode, TexNodeData, TexNodeType, TypstNode, TypstNodeData, TypstNodeType, TypstSupsubData};
-use crate::map::SYMBOL_MAP;
+use crate::command_registry::{LENGTH_COMMANDS, MHCHEM_COMMANDS, SIUNITX_COMMANDS};
+use crate::definitions::{
+    TexNode, TexNodeData, TexNodeType, TexSupsubData, TypstNode, TypstNodeData, TypstNodeType, TypstSupsubData,
+};
+use crate::map::{NEGATED_SYMBOLS, SYMBOL_MAP};
+use crate::mhchem::{convert_mhchem, MhchemTarget};
+use crate::siunitx::{convert_siunitx, SiunitxTarget};
 use std::collections::HashMap;
 
-// Symbols that are supported by Typst but not by KaTeX
+// Operators that Typst provides as built-in symbols, so \operatorname{det} can simply become det
 const TYPST_INTRINSIC_SYMBOLS: &[&str] = &[
-    "dim", "id", "im", "mod", "Pr", "sech", "csch",
+    "arccos", "arcsin", "arctan", "arg", "cos", "cosh", "cot", "coth", "csc", "csch", "ctg", "deg", "det", "dim",
+    "exp", "gcd", "lcm", "hom", "id", "im", "inf", "ker", "lg", "lim", "liminf", "limsup", "ln", "log", "max", "min",
+    "mod", "Pr", "sec", "sech", "sin", "sinc", "sinh", "sup", "tan", "tanh", "tg", "tr",
     // "sgn"
 ];
 
+fn is_single_bar(node: &TexNode) -> bool {
+    node.node_type == TexNodeType::Element && node.content == "|"
+}
+
+fn is_angle(node: &TexNode, name: &str) -> bool {
+    node.node_type == TexNodeType::Symbol && node.content == name
+}
+
+// The bars at the top level of a bra-ket become \middle|, e.g. \phi | A | \psi
+// Bars that open or close the body, or carry scripts, stay absolute values, as in \langle |\psi|^2 \rangle
+fn middle_bars(node: &TexNode) -> TexNode {
+    if node.node_type != TexNodeType::Ordgroup {
+        return node.clone();
+    }
+    let args = node.args.as_ref().unwrap();
+    let mut absolute = Vec::new();
+    for double in [true, false] {
+        let mut pending_open: Option<usize> = None;
+        let mut free: Vec<usize> = Vec::new();
+        for (i, arg) in args.iter().enumerate() {
+            let role = match bar_kind(arg) {
+                Some((is_double, role)) if is_double == double => role,
+                _ => continue,
+            };
+            if let Some(open) = pending_open.filter(|open| i > open + 1 && role != BarRole::Open) {
+                absolute.extend([open, i]);
+                pending_open = None;
+            } else if i == 0 || role == BarRole::Open {
+                pending_open = Some(i);
+            } else if i == args.len() - 1 || role == BarRole::Close {
+                if let Some(open) = free.pop().filter(|open| i > open + 1) {
+                    absolute.extend([open, i]);
+                }
+            } else {
+                free.push(i);
+            }
+        }
+    }
+    let args = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| {
+            let is_bar = arg.node_type == TexNodeType::Element && (arg.content == "|" || arg.content == "\\|");
+            if is_bar && !absolute.contains(&i) {
+                TexNode::new(
+                    TexNodeType::UnaryFunc,
+                    "\\middle".to_string(),
+                    Some(vec![arg.clone()]),
+                    None,
+                )
+            } else {
+                arg.clone()
+            }
+        })
+        .collect();
+    TexNode::new(TexNodeType::Ordgroup, String::new(), Some(args), None)
+}
+
+// Dirac notation written by hand, \langle \psi | -> bra, | \phi \rangle -> ket,
+// and \langle \phi | A | \psi \rangle -> bra-ket, as if written with \left and \right
+fn pair_dirac(args: &[TexNode]) -> Vec<TexNode> {
+    let mut res = Vec::new();
+    let mut pos = 0;
+    while pos < args.len() {
+        let rest = &args[pos + 1..];
+        let next_rangle = rest.iter().position(|arg| is_angle(arg, "\\rangle"));
+        let next_langle = rest.iter().position(|arg| is_angle(arg, "\\langle"));
+        let next_bar = rest.iter().position(is_single_bar);
+        let before = |first: Option<usize>, second: Option<usize>| match (first, second) {
+            (Some(first), Some(second)) => first < second,
+            (Some(_), None) => true,
+            _ => false,
+        };
+        let closing = if is_angle(&args[pos], "\\langle") && before(next_bar, next_langle) {
+            // a bra-ket has its bars before the closing angle, a bra ends with the bar
+            if before(next_rangle, next_langle) && before(next_bar, next_rangle) {
+                next_rangle
+            } else {
+                next_bar
+            }
+        } else if is_single_bar(&args[pos]) && before(next_rangle, next_bar) && before(next_rangle, next_langle) {
+            next_rangle
+        } else {
+            None
+        };
+        let Some(closing) = closing else {
+            res.push(args[pos].clone());
+            pos += 1;
+            continue;
+        };
+        let closing = pos + 1 + closing;
+        let mut body = args[pos + 1..closing].to_vec();
+        let body = if body.len() == 1 {
+            body.remove(0)
+        } else {
+            TexNode::new(TexNodeType::Ordgroup, String::new(), Some(body), None)
+        };
+        let delimiter = |node: &TexNode| TexNode::new(TexNodeType::Element, node.content.clone(), None, None);
+        res.push(TexNode::new(
+            TexNodeType::Leftright,
+            String::new(),
+            Some(vec![delimiter(&args[pos]), body, delimiter(&args[closing])]),
+            None,
+        ));
+        pos = closing + 1;
+    }
+    res
+}
+
+// A bra followed by a ket is a single bra-ket, as the physics package writes \bra{a}\ket{b}
+fn contract_brakets(args: Vec<TexNode>) -> Vec<TexNode> {
+    let delimiters = |node: &TexNode| -> Option<(String, String)> {
+        if node.node_type != TexNodeType::Leftright {
+            return None;
+        }
+        let args = node.args.as_ref().unwrap();
+        Some((args[0].content.clone(), args[2].content.clone()))
+    };
+    let mut res: Vec<TexNode> = Vec::new();
+    for arg in args {
+        let is_ket = delimiters(&arg).is_some_and(|(left, right)| left == "|" && right == "\\rangle");
+        let follows_bra = res
+            .last()
+            .and_then(delimiters)
+            .is_some_and(|(left, right)| left == "\\langle" && right == "|");
+        if !(is_ket && follows_bra) {
+            res.push(arg);
+            continue;
+        }
+        let bra = res.pop().unwrap().args.unwrap();
+        let ket = arg.args.unwrap();
+        let bar = TexNode::new(TexNodeType::Element, "|".to_string(), None, None);
+        let body = TexNode::new(
+            TexNodeType::Ordgroup,
+            String::new(),
+            Some(vec![bra[1].clone(), bar, ket[1].clone()]),
+            None,
+        );
+        res.push(TexNode::new(
+            TexNodeType::Leftright,
+            String::new(),
+            Some(vec![bra[0].clone(), body, ket[2].clone()]),
+            None,
+        ));
+    }
+    res
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum BarRole {
+    Open,
+    Close,
+    Either,
+}
+
+// Whether the node is a double bar, and on which side of a pair it can stand
+fn bar_kind(node: &TexNode) -> Option<(bool, BarRole)> {
+    let (content, close_only) = match node.node_type {
+        TexNodeType::Element | TexNodeType::Symbol => (node.content.as_str(), false),
+        // a bar carrying scripts, as in |x|^2, can only close a pair
+        TexNodeType::SupSub => match node.data.as_ref().unwrap().as_ref() {
+            TexNodeData::Supsub(data) => (data.base.content.as_str(), true),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    let (double, role) = match content {
+        "|" | "\\vert" => (false, BarRole::Either),
+        "\\lvert" => (false, BarRole::Open),
+        "\\rvert" => (false, BarRole::Close),
+        "\\|" | "\\Vert" => (true, BarRole::Either),
+        "\\lVert" => (true, BarRole::Open),
+        "\\rVert" => (true, BarRole::Close),
+        _ => return None,
+    };
+    match (close_only, role) {
+        (false, _) => Some((double, role)),
+        (true, BarRole::Open) => None,
+        (true, _) => Some((double, BarRole::Close)),
+    }
+}
+
+// |x| + |y| -> \left| x \right| + \left| y \right|, so that the bars become abs() and norm()
+// Bars are paired in order, and left untouched unless every bar of the group finds a non-empty pair
+fn pair_bars(args: &[TexNode]) -> Vec<TexNode> {
+    let mut args = args.to_vec();
+    for double in [true, false] {
+        let bars: Vec<(usize, BarRole)> = args
+            .iter()
+            .enumerate()
+            .filter_map(|(i, arg)| match bar_kind(arg) {
+                Some((is_double, role)) if is_double == double => Some((i, role)),
+                _ => None,
+            })
+            .collect();
+        if bars.is_empty()
+            || !bars.len().is_multiple_of(2)
+            || bars
+                .chunks(2)
+                .any(|pair| pair[0].1 == BarRole::Close || pair[1].1 == BarRole::Open || pair[1].0 == pair[0].0 + 1)
+        {
+            continue;
+        }
+
+        let mut paired = Vec::new();
+        let mut pos = 0;
+        for pair in bars.chunks(2) {
+            let (open, close) = (pair[0].0, pair[1].0);
+            paired.extend_from_slice(&args[pos..open]);
+            let mut body = args[open + 1..close].to_vec();
+            let body = if body.len() == 1 {
+                body.remove(0)
+            } else {
+                TexNode::new(TexNodeType::Ordgroup, String::new(), Some(body), None)
+            };
+            let delimiter = |node: &TexNode| TexNode::new(TexNodeType::Element, node.content.clone(), None, None);
+            let closing = &args[close];
+            paired.push(match closing.data.as_ref().map(|data| data.as_ref()) {
+                Some(TexNodeData::Supsub(data)) => TexNode::new(
+                    TexNodeType::SupSub,
+                    String::new(),
+                    None,
+                    Some(Box::from(TexNodeData::Supsub(TexSupsubData {
+                        base: TexNode::new(
+                            TexNodeType::Leftright,
+                            String::new(),
+                            Some(vec![delimiter(&args[open]), body, delimiter(&data.base)]),
+                            None,
+                        ),
+                        sup: data.sup.clone(),
+                        sub: data.sub.clone(),
+                    }))),
+                ),
+                _ => TexNode::new(
+                    TexNodeType::Leftright,
+                    String::new(),
+                    Some(vec![delimiter(&args[open]), body, delimiter(closing)]),
+                    None,
+                ),
+            });
+            pos = close + 1;
+        }
+        paired.extend_from_slice(&args[pos..]);
+        args = paired;
+    }
+    args
+}
+
+// Binary operators and relations around which amsmath centers \dots
+const BINARY_OR_RELATION_COMMANDS: &[&str] = &[
+    "approx",
+    "ast",
+    "bullet",
+    "cap",
+    "cdot",
+    "circ",
+    "cong",
+    "cup",
+    "div",
+    "equiv",
+    "ge",
+    "geq",
+    "gets",
+    "gg",
+    "iff",
+    "implies",
+    "in",
+    "land",
+    "le",
+    "leq",
+    "ll",
+    "lor",
+    "mapsto",
+    "mp",
+    "ne",
+    "neq",
+    "ni",
+    "odot",
+    "ominus",
+    "oplus",
+    "otimes",
+    "pm",
+    "prec",
+    "preceq",
+    "propto",
+    "setminus",
+    "sim",
+    "simeq",
+    "star",
+    "subset",
+    "subseteq",
+    "succ",
+    "succeq",
+    "supset",
+    "supseteq",
+    "times",
+    "to",
+    "vee",
+    "wedge",
+    "Leftarrow",
+    "Leftrightarrow",
+    "Rightarrow",
+    "leftarrow",
+    "leftrightarrow",
+    "rightarrow",
+];
+
+fn is_binary_or_relation(node: &TexNode) -> bool {
+    match node.node_type {
+        TexNodeType::Element => matches!(node.content.as_str(), "+" | "-" | "*" | "=" | "<" | ">"),
+        TexNodeType::Symbol => BINARY_OR_RELATION_COMMANDS.contains(&&node.content[1..]),
+        _ => false,
+    }
+}
+
+// a_1 + \dots + a_n -> dots.c, a_1, \dots, a_n -> dots.h
+// The dots are centered before a binary operator or relation, or after one when they end the group
+fn resolve_dots(siblings: &[TexNode], index: usize) -> TypstNode {
+    let centered = match siblings.get(index + 1) {
+        Some(next) => is_binary_or_relation(next),
+        None => index > 0 && is_binary_or_relation(&siblings[index - 1]),
+    };
+    let symbol = if centered { "dots.c" } else { "dots.h" };
+    TypstNode::new(TypstNodeType::Symbol, symbol.to_string(), None, None)
+}
+
+#[derive(Debug, Clone)]
+pub struct ConverterOptions {
+    // \int f(x) dx -> integral f(x) dif x, \frac{dy}{dx} -> (dif y)/(dif x)
+    pub detect_differentials: bool,
+    // keep the italic d of the source for detected differentials, only adding the thin space in integrals
+    pub italic_differential: bool,
+    // x_{max} -> x_"max", \mathrm{abc} -> upright("abc")
+    pub multi_letter_text: bool,
+    // runs of letters that multi_letter_text always keeps as products of variables, e.g. a_{ab}
+    pub variable_products: Vec<String>,
+    // the conventions followed for bold and italic alphabets
+    pub style_preset: StylePreset,
+    // plain math or the unify package for \SI, \num and the other siunitx commands
+    pub siunitx_target: SiunitxTarget,
+    // plain math or the typsium package for \ce
+    pub mhchem_target: MhchemTarget,
+}
+
+impl Default for ConverterOptions {
+    fn default() -> Self {
+        ConverterOptions {
+            detect_differentials: false,
+            italic_differential: false,
+            multi_letter_text: false,
+            variable_products: Vec::new(),
+            style_preset: StylePreset::Tex,
+            siunitx_target: SiunitxTarget::Math,
+            mhchem_target: MhchemTarget::Math,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum StylePreset {
+    // bold letters are upright and \mathbf leaves lowercase Greek alone, as TeX typesets them
+    #[default]
+    Tex,
+    // bold letters keep the italic of variables, as ISO 80000-2 wants for vectors and matrices
+    Iso,
+}
+
+const LOWERCASE_GREEK: &[&str] = &[
+    "alpha",
+    "beta",
+    "gamma",
+    "delta",
+    "epsilon",
+    "varepsilon",
+    "zeta",
+    "eta",
+    "theta",
+    "vartheta",
+    "iota",
+    "kappa",
+    "varkappa",
+    "lambda",
+    "mu",
+    "nu",
+    "xi",
+    "omicron",
+    "pi",
+    "varpi",
+    "rho",
+    "varrho",
+    "sigma",
+    "varsigma",
+    "tau",
+    "upsilon",
+    "phi",
+    "varphi",
+    "chi",
+    "psi",
+    "omega",
+];
+
+const UPPERCASE_GREEK: &[&str] = &[
+    "Gamma",
+    "Delta",
+    "Theta",
+    "Lambda",
+    "Xi",
+    "Pi",
+    "Sigma",
+    "Upsilon",
+    "Phi",
+    "Psi",
+    "Omega",
+    "varGamma",
+    "varDelta",
+    "varTheta",
+    "varLambda",
+    "varXi",
+    "varPi",
+    "varSigma",
+    "varUpsilon",
+    "varPhi",
+    "varPsi",
+    "varOmega",
+];
+
+fn is_greek(node: &TexNode, alphabet: &[&str]) -> bool {
+    node.node_type == TexNodeType::Symbol && alphabet.contains(&&node.content[1..])
+}
+
+// TeX: \mathbf{x} -> upright(bold(x)), \mathbf{\alpha} -> alpha since \mathbf has no lowercase Greek
+// ISO: \mathbf{x} -> bold(x), \mathbf{\Gamma} -> bold(italic(Gamma))
+fn convert_mathbf(arg: &TexNode, arg0: TypstNode, options: &ConverterOptions) -> TypstNode {
+    let func_call =
+        |name: &str, arg: TypstNode| TypstNode::new(TypstNodeType::FuncCall, name.to_string(), Some(vec![arg]), None);
+    match options.style_preset {
+        StylePreset::Tex if is_greek(arg, LOWERCASE_GREEK) => arg0,
+        StylePreset::Tex => func_call("upright", func_call("bold", arg0)),
+        StylePreset::Iso if is_greek(arg, UPPERCASE_GREEK) => func_call("bold", func_call("italic", arg0)),
+        StylePreset::Iso => func_call("bold", arg0),
+    }
+}
+
+// Font commands whose argument is usually a word rather than a product of variables
+const TEXT_LIKE_COMMANDS: &[&str] = &["\\mathrm", "\\mathit", "\\mathsf", "\\mathtt"];
+
+fn is_letter(node: &TexNode) -> bool {
+    node.node_type == TexNodeType::Element && node.content.chars().all(|c| c.is_ascii_alphabetic())
+}
+
+// Words commonly found in subscripts, which would otherwise pass for products of indices
+const SUBSCRIPT_WORDS: &[&str] = &[
+    "in", "out", "on", "off", "ex", "eq", "th", "max", "min", "sup", "inf", "rms", "avg", "tot", "eff", "ext", "int",
+    "ref", "obs", "exp", "est", "opt", "crit", "init", "rel", "abs", "kin", "pot",
+];
+
+// Letters usually standing for tensor indices or coordinates, e.g. R_{ijkl}, g_{mn} or A_{xyz}
+const INDEX_LETTERS: &str = "ijklmnpqrstuvwxyz";
+
+// Inside \mathrm-like commands every run is a word, in a subscript only known words and longer runs
+// that are not made of index letters are, so that R_{ijkl} stays a product of indices
+fn is_text_run(run: &str, in_subscript: bool, options: &ConverterOptions) -> bool {
+    if run.len() < 2 || options.variable_products.iter().any(|product| product == run) {
+        return false;
+    }
+    !in_subscript || SUBSCRIPT_WORDS.contains(&run) || run.len() >= 3 && !run.chars().all(|c| INDEX_LETTERS.contains(c))
+}
+
+// With multi_letter_text, runs of letters in the group that form words become quoted text, e.g. {i,max} -> i, "max"
+fn convert_letter_runs(node: &TexNode, options: &ConverterOptions, in_subscript: bool) -> Result<TypstNode, String> {
+    if !options.multi_letter_text || node.node_type != TexNodeType::Ordgroup {
+        return convert_tree_with_options(node, options);
+    }
+    let args = node.args.as_ref().unwrap();
+    let mut res = Vec::new();
+    let mut pos = 0;
+    while pos < args.len() {
+        let run_end = pos + args[pos..].iter().take_while(|arg| is_letter(arg)).count();
+        let run = args[pos..run_end]
+            .iter()
+            .map(|arg| arg.content.as_str())
+            .collect::<String>();
+        if is_text_run(&run, in_subscript, options) {
+            res.push(TypstNode::new(TypstNodeType::Text, run, None, None));
+            pos = run_end;
+        } else {
+            // a product of variables is kept letter by letter
+            for arg in &args[pos..run_end.max(pos + 1)] {
+                res.push(convert_tree_with_options(arg, options)?);
+            }
+            pos = run_end.max(pos + 1);
+        }
+    }
+    if res.len() == 1 {
+        return Ok(res.remove(0));
+    }
+    Ok(TypstNode::new(TypstNodeType::Group, "".to_string(), Some(res), None))
+}
+
+fn is_differential_d(node: &TexNode) -> bool {
+    match node.node_type {
+        TexNodeType::Element | TexNodeType::Text => node.content == "d",
+        // \mathrm{d}, {\rm d}, \operatorname{d}
+        TexNodeType::UnaryFunc => {
+            matches!(node.content.as_str(), "\\mathrm" | "\\rm" | "\\operatorname" | "\\text")
+                && is_differential_d(&node.args.as_ref().unwrap()[0])
+        }
+        _ => false,
+    }
+}
+
+fn is_differential_variable(node: &TexNode) -> bool {
+    match node.node_type {
+        TexNodeType::Element => node.content.chars().all(|c| c.is_alphabetic()),
+        TexNodeType::Symbol => true,
+        TexNodeType::SupSub => match node.data.as_ref().unwrap().as_ref() {
+            TexNodeData::Supsub(data) => is_differential_variable(&data.base),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn is_integral(node: &TexNode) -> bool {
+    match node.node_type {
+        TexNodeType::Symbol => matches!(
+            node.content.as_str(),
+            "\\int" | "\\iint" | "\\iiint" | "\\iiiint" | "\\oint" | "\\oiint" | "\\oiiint"
+        ),
+        TexNodeType::SupSub => match node.data.as_ref().unwrap().as_ref() {
+            TexNodeData::Supsub(data) => is_integral(&data.base),
+            _ => false,
+        },
+        TexNodeType::UnaryFunc => is_limits_modifier(node) && is_integral(&node.args.as_ref().unwrap()[0]),
+        _ => false,
+    }
+}
+
+fn dif_node() -> TexNode {
+    TexNode::new(TexNodeType::Symbol, "\\dif".to_string(), None, None)
+}
+
+// Relations and the signs between terms, which end the integrand they follow
+const INTEGRAND_END_COMMANDS: &[&str] = &[
+    "pm", "mp", "approx", "cong", "equiv", "ge", "geq", "le", "leq", "ne", "neq", "sim", "simeq", "to", "implies",
+    "iff",
+];
+
+fn ends_integrand(node: &TexNode) -> bool {
+    match node.node_type {
+        TexNodeType::Element => matches!(node.content.as_str(), "+" | "-" | "=" | "<" | ">"),
+        TexNodeType::Symbol => INTEGRAND_END_COMMANDS.contains(&&node.content[1..]),
+        _ => false,
+    }
+}
+
+// In an integrand, d x -> \dif x, and the explicit thin space before it is dropped since dif has its own
+// The integrand ends at a sign or relation, or at whatever follows its differentials, e.g. \int dx f(x) + g dy
+fn mark_integrand_differentials(args: &[TexNode], options: &ConverterOptions) -> Vec<TexNode> {
+    let mut res: Vec<TexNode> = Vec::new();
+    let mut in_integrand = false;
+    // the position of the variable of the last differential, once one has been found
+    let mut last_variable: Option<usize> = None;
+    for (i, arg) in args.iter().enumerate() {
+        if is_integral(arg) {
+            in_integrand = true;
+            last_variable = None;
+        } else if ends_integrand(arg)
+            || last_variable.is_some_and(|variable| {
+                i > variable && arg.node_type != TexNodeType::Control && !is_differential_d(arg)
+            })
+        {
+            in_integrand = false;
+            last_variable = None;
+        }
+        let is_differential =
+            in_integrand && is_differential_d(arg) && args.get(i + 1).is_some_and(is_differential_variable);
+        if !is_differential {
+            res.push(arg.clone());
+            continue;
+        }
+        last_variable = Some(i + 1);
+        let thin_space_before = res
+            .last()
+            .is_some_and(|last| last.node_type == TexNodeType::Control && last.content == "\\,");
+        if options.italic_differential {
+            if !thin_space_before {
+                res.push(TexNode::new(TexNodeType::Control, "\\,".to_string(), None, None));
+            }
+            res.push(arg.clone());
+        } else {
+            if thin_space_before {
+                res.pop();
+            }
+            res.push(dif_node());
+        }
+    }
+    res
+}
+
+// Replace the d that starts one side of a derivative fraction, e.g. d, d^2 or d y
+fn replace_leading_d(node: &TexNode) -> Option<TexNode> {
+    if is_differential_d(node) {
+        return Some(dif_node());
+    }
+    match node.node_type {
+        TexNodeType::SupSub => {
+            let TexNodeData::Supsub(data) = node.data.as_ref().unwrap().as_ref() else {
+                return None;
+            };
+            if !is_differential_d(&data.base) {
+                return None;
+            }
+            let mut res = node.clone();
+            res.data = Some(Box::from(TexNodeData::Supsub(TexSupsubData {
+                base: dif_node(),
+                sup: data.sup.clone(),
+                sub: data.sub.clone(),
+            })));
+            Some(res)
+        }
+        TexNodeType::Ordgroup => {
+            let args = node.args.as_ref().unwrap();
+            let first = replace_leading_d(args.first()?)?;
+            let mut res = node.clone();
+            res.args.as_mut().unwrap()[0] = first;
+            Some(res)
+        }
+        _ => None,
+    }
+}
+
+// \frac{d}{dx} -> \frac{\dif}{\dif x}, \frac{d^2 y}{dx^2} -> \frac{\dif^2 y}{\dif x^2}
+fn mark_derivative_differentials(num: &TexNode, den: &TexNode) -> Option<(TexNode, TexNode)> {
+    let den_args = den.args.as_ref().filter(|_| den.node_type == TexNodeType::Ordgroup)?;
+    if den_args.len() < 2 || !is_differential_d(&den_args[0]) || !is_differential_variable(&den_args[1]) {
+        return None;
+    }
+    Some((replace_leading_d(num)?, replace_leading_d(den)?))
+}
+
 pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
+    convert_tree_with_options(node, &ConverterOptions::default())
+}
+
+pub fn convert_tree_with_options(node: &TexNode, options: &ConverterOptions) -> Result<TypstNode, String> {
     match node.node_type {
         TexNodeType::Empty => Ok(TypstNode::new(TypstNodeType::Empty, String::from(""), None, None)),
         TexNodeType::Whitespace => Ok(TypstNode::new(
@@ -23,25 +675,37 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
             None,
             None,
         )),
-        TexNodeType::Ordgroup => Ok(TypstNode::new(
-            TypstNodeType::Group,
-            String::from(""),
-            Some(
-                node.args
-                    .as_ref()
-                    .unwrap()
-                    .iter()
-                    .map(|arg| convert_tree(arg))
-                    .collect::<Result<Vec<_>, String>>()?,
-            ),
-            None,
-        )),
+        TexNodeType::Ordgroup => {
+            let mut args = pair_bars(&contract_brakets(pair_dirac(node.args.as_ref().unwrap())));
+            if options.detect_differentials {
+                args = mark_integrand_differentials(&args, options);
+            }
+            let args = args.as_slice();
+            Ok(TypstNode::new(
+                TypstNodeType::Group,
+                String::from(""),
+                Some(
+                    args.iter()
+                        .enumerate()
+                        .map(|(i, arg)| {
+                            if arg.node_type == TexNodeType::Symbol && arg.content == "\\dots" {
+                                Ok(resolve_dots(args, i))
+                            } else {
+                                convert_tree_with_options(arg, options)
+                            }
+                        })
+                        .collect::<Result<Vec<_>, String>>()?,
+                ),
+                None,
+            ))
+        }
         TexNodeType::Element => Ok(TypstNode::new(
             TypstNodeType::Atom,
             convert_token(&node.content),
             None,
             None,
         )),
+        TexNodeType::Symbol if node.content == "\\bmod" => Ok(convert_bmod()),
         TexNodeType::Symbol => Ok(TypstNode::new(
             TypstNodeType::Symbol,
             convert_token(&node.content),
@@ -64,8 +728,8 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                     TypstNodeType::FuncCall,
                     "overbrace".to_string(),
                     Some(vec![
-                        convert_tree(&base.args.as_ref().unwrap()[0])?,
-                        convert_tree(sup.unwrap())?,
+                        convert_tree_with_options(&base.args.as_ref().unwrap()[0], options)?,
+                        convert_tree_with_options(sup.unwrap(), options)?,
                     ]),
                     None,
                 ));
@@ -74,15 +738,19 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                     TypstNodeType::FuncCall,
                     "underbrace".to_string(),
                     Some(vec![
-                        convert_tree(&base.args.as_ref().unwrap()[0])?,
-                        convert_tree(sub.unwrap())?,
+                        convert_tree_with_options(&base.args.as_ref().unwrap()[0], options)?,
+                        convert_tree_with_options(sub.unwrap(), options)?,
                     ]),
                     None,
                 ));
             }
 
             let mut typst_data = TypstSupsubData {
-                base: convert_tree(base)?,
+                base: if is_limits_modifier(base) {
+                    convert_limits_modifier(base, true, options)?
+                } else {
+                    convert_tree_with_options(base, options)?
+                },
                 sup: None,
                 sub: None,
             };
@@ -91,10 +759,10 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                 typst_data.base = TypstNode::new(TypstNodeType::Text, "".to_string(), None, None);
             }
             if let Some(sup) = sup {
-                typst_data.sup = Some(convert_tree(sup)?);
+                typst_data.sup = Some(convert_tree_with_options(sup, options)?);
             }
             if let Some(sub) = sub {
-                typst_data.sub = Some(convert_tree(sub)?);
+                typst_data.sub = Some(convert_letter_runs(sub, options, true)?);
             }
 
             Ok(TypstNode::new(
@@ -106,16 +774,36 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
         }
         TexNodeType::Leftright => {
             let args = node.args.as_ref().unwrap();
+            // \left| x \right| -> abs(x), \left\| v \right\| -> norm(v)
+            let bar_function = match (args[0].content.as_str(), args[2].content.as_str()) {
+                ("|" | "\\vert" | "\\lvert", "|" | "\\vert" | "\\rvert") => Some("abs"),
+                ("\\|" | "\\Vert" | "\\lVert", "\\|" | "\\Vert" | "\\rVert") => Some("norm"),
+                _ => None,
+            };
+            if let Some(bar_function) = bar_function {
+                return Ok(TypstNode::new(
+                    TypstNodeType::FuncCall,
+                    bar_function.to_string(),
+                    Some(vec![convert_tree_with_options(&args[1], options)?]),
+                    None,
+                ));
+            }
             let left = &args[0];
             let right = &args[2];
+            // \left\langle \phi | A | \psi \right\rangle -> lr(angle.l phi mid(|) A mid(|) psi angle.r)
+            let body = if left.content == "\\langle" || right.content == "\\rangle" {
+                middle_bars(&args[1])
+            } else {
+                args[1].clone()
+            };
             let mut group = TypstNode::new(
                 TypstNodeType::Group,
                 "".to_string(),
-                Some(
-                    args.iter()
-                        .map(|arg| convert_tree(arg))
-                        .collect::<Result<Vec<_>, String>>()?,
-                ),
+                Some(vec![
+                    convert_tree_with_options(left, options)?,
+                    convert_tree_with_options(&body, options)?,
+                    convert_tree_with_options(right, options)?,
+                ]),
                 None,
             );
             if matches!(
@@ -153,7 +841,7 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
             if node.content == "\\sqrt" {
                 match node.args.as_ref().unwrap().len() {
                     1 => {
-                        let mandatory_arg = convert_tree(&node.args.as_ref().unwrap()[0])?;
+                        let mandatory_arg = convert_tree_with_options(&node.args.as_ref().unwrap()[0], options)?;
                         Ok(TypstNode::new(
                             TypstNodeType::FuncCall,
                             "sqrt".to_string(),
@@ -162,8 +850,8 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                         ))
                     }
                     2 => {
-                        let optional_arg = convert_tree(&node.args.as_ref().unwrap()[0])?;
-                        let mandatory_arg = convert_tree(&node.args.as_ref().unwrap()[1])?;
+                        let optional_arg = convert_tree_with_options(&node.args.as_ref().unwrap()[0], options)?;
+                        let mandatory_arg = convert_tree_with_options(&node.args.as_ref().unwrap()[1], options)?;
                         Ok(TypstNode::new(
                             TypstNodeType::FuncCall,
                             "root".to_string(),
@@ -176,20 +864,89 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                         node.args.as_ref().unwrap().len()
                     )),
                 }
+            } else if node.content == "\\smash" {
+                // Typst cannot smash only the top or the bottom, so [t] and [b] smash both
+                let body = convert_tree_with_options(node.args.as_ref().unwrap().last().unwrap(), options)?;
+                Ok(zero_size_box("height", body))
             } else {
                 Err(format!("Unknown option binary function: {}", node.content))
             }
         }
         TexNodeType::BinaryFunc => {
             if node.content == "\\overset" {
-                return convert_overset(node);
+                return convert_overset(node, options);
+            }
+
+            // \cancelto{0}{x} -> cancel(x)^0
+            if node.content == "\\cancelto" {
+                let args = node.args.as_ref().unwrap();
+                let target = convert_tree_with_options(&args[0], options)?;
+                let body = convert_tree_with_options(&args[1], options)?;
+                return Ok(TypstNode::new(
+                    TypstNodeType::Supsub,
+                    "".to_string(),
+                    None,
+                    Some(Box::from(TypstNodeData::Supsub(TypstSupsubData {
+                        base: TypstNode::new(TypstNodeType::FuncCall, "cancel".to_string(), Some(vec![body]), None),
+                        sup: Some(target),
+                        sub: None,
+                    }))),
+                ));
+            }
+
+            // \textcolor{red}{x} -> text(x, fill: red), \colorbox{red}{x} -> box(x, fill: red, inset: #3pt)
+            // Calling the functions in math mode keeps the body as math, where text(fill: red)[$x$] would need a
+            // content block holding a nested equation
+            if matches!(node.content.as_str(), "\\color" | "\\textcolor" | "\\colorbox") {
+                let args = node.args.as_ref().unwrap();
+                let fill = convert_color(&args[0])?;
+                let body = convert_tree_with_options(&args[1], options)?;
+                let mut options = HashMap::from([("fill".to_string(), fill)]);
+                let function = if node.content == "\\colorbox" {
+                    // \fboxsep defaults to 3pt
+                    options.insert("inset".to_string(), "#3pt".to_string());
+                    "box"
+                } else if is_empty_body(&body) {
+                    // there is no text to color
+                    return Ok(body);
+                } else {
+                    "text"
+                };
+                let body = if is_empty_body(&body) { vec![] } else { vec![body] };
+                let mut res = TypstNode::new(TypstNodeType::FuncCall, function.to_string(), Some(body), None);
+                res.set_options(options);
+                return Ok(res);
+            }
+
+            // \mathversion{bold} a -> bold(a), other versions only change the font family
+            if node.content == "\\mathversion" {
+                let args = node.args.as_ref().unwrap();
+                let body = convert_tree_with_options(&args[1], options)?;
+                return if args[0].content == "bold" {
+                    Ok(TypstNode::new(
+                        TypstNodeType::FuncCall,
+                        "bold".to_string(),
+                        Some(vec![body]),
+                        None,
+                    ))
+                } else {
+                    Ok(body)
+                };
+            }
+
+            if SIUNITX_COMMANDS.contains(&&node.content[1..]) {
+                return convert_siunitx_node(node, options);
             }
 
             // \frac{a}{b} -> a / b
             if node.content == "\\frac" {
                 let args = node.args.as_ref().unwrap();
-                let num = convert_tree(&args[0])?;
-                let den = convert_tree(&args[1])?;
+                let (num, den) = match mark_derivative_differentials(&args[0], &args[1]) {
+                    Some((num, den)) if options.detect_differentials && !options.italic_differential => (num, den),
+                    _ => (args[0].clone(), args[1].clone()),
+                };
+                let num = convert_tree_with_options(&num, options)?;
+                let den = convert_tree_with_options(&den, options)?;
                 return Ok(TypstNode::new(
                     TypstNodeType::Fraction,
                     "".to_string(),
@@ -198,6 +955,28 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                 ));
             }
 
+            // {a \atop b} -> mat(delim: #none, a; b), \brack and \brace only change the delimiters
+            if matches!(node.content.as_str(), "\\atop" | "\\brack" | "\\brace") {
+                let args = node.args.as_ref().unwrap();
+                let rows = vec![
+                    vec![convert_tree_with_options(&args[0], options)?],
+                    vec![convert_tree_with_options(&args[1], options)?],
+                ];
+                let delim = match node.content.as_str() {
+                    "\\brack" => "\"[\"",
+                    "\\brace" => "\"{\"",
+                    _ => "#none",
+                };
+                let mut res = TypstNode::new(
+                    TypstNodeType::Matrix,
+                    "".to_string(),
+                    None,
+                    Some(Box::from(TypstNodeData::Array(rows))),
+                );
+                res.set_options(HashMap::from([("delim".to_string(), delim.to_string())]));
+                return Ok(res);
+            }
+
             Ok(TypstNode::new(
                 TypstNodeType::FuncCall,
                 convert_token(&node.content),
@@ -206,19 +985,38 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                         .as_ref()
                         .ok_or("Binary function node does not have args")?
                         .iter()
-                        .map(|arg| convert_tree(arg))
+                        .map(|arg| convert_tree_with_options(arg, options))
                         .collect::<Result<Vec<_>, String>>()?,
                 ),
                 None,
             ))
         }
         TexNodeType::UnaryFunc => {
-            let arg0 = convert_tree(&node.args.as_ref().unwrap()[0])?;
-            if node.content == "\\mathbf" {
-                let inner = TypstNode::new(TypstNodeType::FuncCall, "bold".to_string(), Some(vec![arg0]), None);
+            if is_limits_modifier(node) {
+                return convert_limits_modifier(node, false, options);
+            }
+            if SIUNITX_COMMANDS.contains(&&node.content[1..]) {
+                return convert_siunitx_node(node, options);
+            }
+            if MHCHEM_COMMANDS.contains(&&node.content[1..]) {
+                let text = &node.args.as_ref().unwrap()[0].content;
+                return convert_mhchem(&node.content, text, options.mhchem_target);
+            }
+            let arg0 = if TEXT_LIKE_COMMANDS.contains(&node.content.as_str()) {
+                convert_letter_runs(&node.args.as_ref().unwrap()[0], options, false)?
+            } else {
+                convert_tree_with_options(&node.args.as_ref().unwrap()[0], options)?
+            };
+            if node.content == "\\mathbf" || node.content == "\\bf" {
+                return Ok(convert_mathbf(&node.args.as_ref().unwrap()[0], arg0, options));
+            }
+            // \mathbfit{x} -> bold(italic(x)), \mathsfit{x} -> sans(italic(x))
+            if node.content == "\\mathbfit" || node.content == "\\mathsfit" {
+                let function = if node.content == "\\mathbfit" { "bold" } else { "sans" };
+                let inner = TypstNode::new(TypstNodeType::FuncCall, "italic".to_string(), Some(vec![arg0]), None);
                 return Ok(TypstNode::new(
                     TypstNodeType::FuncCall,
-                    "upright".to_string(),
+                    function.to_string(),
                     Some(vec![inner]),
                     None,
                 ));
@@ -234,6 +1032,83 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                     None,
                 ));
             }
+            // \boxed{x} -> box(x, inset: #3pt, stroke: #0.4pt), same as the default \fboxsep and \fboxrule
+            // Called in math mode, box keeps x as math, so there is no need for #box(...)[$x$]
+            if node.content == "\\boxed" || node.content == "\\fbox" {
+                // an empty box only draws its frame
+                let body = if is_empty_body(&arg0) { vec![] } else { vec![arg0] };
+                let mut res = TypstNode::new(TypstNodeType::FuncCall, "box".to_string(), Some(body), None);
+                res.set_options(HashMap::from([
+                    ("inset".to_string(), "#3pt".to_string()),
+                    ("stroke".to_string(), "#0.4pt".to_string()),
+                ]));
+                return Ok(res);
+            }
+            if matches!(node.content.as_str(), "\\cancel" | "\\bcancel" | "\\xcancel") {
+                let mut res = TypstNode::new(TypstNodeType::FuncCall, "cancel".to_string(), Some(vec![arg0]), None);
+                if node.content == "\\bcancel" {
+                    res.set_options(HashMap::from([("inverted".to_string(), "#true".to_string())]));
+                } else if node.content == "\\xcancel" {
+                    res.set_options(HashMap::from([("cross".to_string(), "#true".to_string())]));
+                }
+                return Ok(res);
+            }
+            if node.content == "\\not" {
+                return Ok(convert_not(arg0));
+            }
+            if node.content == "\\middle" {
+                return Ok(TypstNode::new(
+                    TypstNodeType::FuncCall,
+                    "mid".to_string(),
+                    Some(vec![arg0]),
+                    None,
+                ));
+            }
+            if matches!(node.content.as_str(), "\\mod" | "\\pmod" | "\\pod") {
+                return Ok(convert_modulo(&node.content, arg0));
+            }
+            if LENGTH_COMMANDS.contains(&&node.content[1..]) {
+                return convert_spacing(&node.args.as_ref().unwrap()[0].content);
+            }
+            // \phantom{x} -> hide(x), the h and v variants keep only the width or the height of x
+            if matches!(node.content.as_str(), "\\phantom" | "\\hphantom" | "\\vphantom") {
+                let hidden = TypstNode::new(TypstNodeType::FuncCall, "hide".to_string(), Some(vec![arg0]), None);
+                return Ok(match node.content.as_str() {
+                    "\\hphantom" => zero_size_box("height", hidden),
+                    "\\vphantom" => zero_size_box("width", hidden),
+                    _ => hidden,
+                });
+            }
+            // \mathrlap{x} -> box(width: #0pt, x), content of a zero width box sticks out to the right
+            if node.content == "\\mathrlap" || node.content == "\\rlap" {
+                return Ok(zero_size_box("width", arg0));
+            }
+            if let Some(alignment) = lap_alignment(&node.content) {
+                let alignment = TypstNode::new(TypstNodeType::Symbol, alignment.to_string(), None, None);
+                let aligned = TypstNode::new(
+                    TypstNodeType::FuncCall,
+                    "align".to_string(),
+                    Some(vec![alignment, arg0]),
+                    None,
+                );
+                return Ok(zero_size_box("width", aligned));
+            }
+            if node.content == "\\mathop" {
+                let mut op_call = TypstNode::new(TypstNodeType::FuncCall, "op".to_string(), Some(vec![arg0]), None);
+                op_call.set_options(HashMap::from([("limits".to_string(), "#true".to_string())]));
+                return Ok(op_call);
+            }
+            if let Some(class) = math_class(&node.content) {
+                return Ok(TypstNode::new(
+                    TypstNodeType::FuncCall,
+                    "class".to_string(),
+                    Some(vec![
+                        TypstNode::new(TypstNodeType::Text, class.to_string(), None, None),
+                        arg0,
+                    ]),
+                    None,
+                ));
+            }
             if node.content == "\\operatorname" {
                 let body = node.args.as_ref().unwrap();
                 if body.len() != 1 || body[0].node_type != TexNodeType::Text {
@@ -242,7 +1117,14 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                         node
                     ));
                 }
-                let text = &body[0].content;
+                // spacing commands such as in \operatorname{arg\,max} become plain spaces
+                let text = &body[0]
+                    .content
+                    .replace("\\,", " ")
+                    .replace("\\;", " ")
+                    .replace("\\:", " ")
+                    .replace("\\ ", " ")
+                    .replace('~', " ");
                 return if TYPST_INTRINSIC_SYMBOLS.contains(&text.as_str()) {
                     Ok(TypstNode::new(TypstNodeType::Symbol, text.to_string(), None, None))
                 } else {
@@ -257,14 +1139,7 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
             Ok(TypstNode::new(
                 TypstNodeType::FuncCall,
                 convert_token(&node.content),
-                Some(
-                    node.args
-                        .as_ref()
-                        .unwrap()
-                        .iter()
-                        .map(|arg| convert_tree(arg))
-                        .collect::<Result<Vec<_>, String>>()?,
-                ),
+                Some(vec![arg0]),
                 None,
             ))
         }
@@ -274,7 +1149,11 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
             };
             let data: Vec<Vec<TypstNode>> = matrix
                 .iter()
-                .map(|row| row.iter().map(|n| convert_tree(n)).collect::<Result<Vec<_>, String>>())
+                .map(|row| {
+                    row.iter()
+                        .map(|n| convert_tree_with_options(n, options))
+                        .collect::<Result<Vec<_>, String>>()
+                })
                 .collect::<Result<_, String>>()?;
             if node.content.starts_with("align") {
                 Ok(TypstNode::new(
@@ -305,6 +1184,14 @@ pub fn convert_tree(node: &TexNode) -> Result<TypstNode, String> {
                 Ok(TypstNode::new(TypstNodeType::Symbol, "\\".to_string(), None, None))
             } else if node.content == "\\," {
                 Ok(TypstNode::new(TypstNodeType::Symbol, "thin".to_string(), None, None))
+            } else if node.content == "\\:" || node.content == "\\>" {
+                Ok(TypstNode::new(TypstNodeType::Symbol, "med".to_string(), None, None))
+            } else if node.content == "\\;" {
+                Ok(TypstNode::new(TypstNodeType::Symbol, "thick".to_string(), None, None))
+            } else if node.content == "\\!" {
+                convert_spacing("-3mu")
+            } else if node.content == "\\ " {
+                Ok(TypstNode::new(TypstNodeType::Symbol, "space".to_string(), None, None))
             } else {
                 return Err(format!("Unknown control sequence: {:?}", node));
             }
@@ -345,7 +1232,312 @@ fn convert_token(token: &str) -> String {
     }
 }
 
-fn convert_overset(node: &TexNode) -> Result<TypstNode, String> {
+fn convert_siunitx_node(node: &TexNode, options: &ConverterOptions) -> Result<TypstNode, String> {
+    let args: Vec<String> = node
+        .args
+        .as_ref()
+        .unwrap()
+        .iter()
+        .map(|arg| arg.content.clone())
+        .collect();
+    convert_siunitx(&node.content, &args, options.siunitx_target)
+}
+
+// \pmod{n} -> space (mod n), \pod{n} -> space (n), \mod{n} -> quad mod space n
+fn convert_modulo(command: &str, modulus: TypstNode) -> TypstNode {
+    let symbol = |name: &str| TypstNode::new(TypstNodeType::Symbol, name.to_string(), None, None);
+    let atom = |content: &str| TypstNode::new(TypstNodeType::Atom, content.to_string(), None, None);
+    let args = match command {
+        "\\mod" => vec![symbol("quad"), symbol("mod"), symbol("space"), modulus],
+        "\\pmod" => vec![symbol("space"), atom("("), symbol("mod"), modulus, atom(")")],
+        _ => vec![symbol("space"), atom("("), modulus, atom(")")],
+    };
+    TypstNode::new(TypstNodeType::Group, "".to_string(), Some(args), None)
+}
+
+// a \bmod b -> a class("binary", mod) b, Typst's mod is an operator name without binary spacing
+fn convert_bmod() -> TypstNode {
+    TypstNode::new(
+        TypstNodeType::FuncCall,
+        "class".to_string(),
+        Some(vec![
+            TypstNode::new(TypstNodeType::Text, "binary".to_string(), None, None),
+            TypstNode::new(TypstNodeType::Symbol, "mod".to_string(), None, None),
+        ]),
+        None,
+    )
+}
+
+// \not= -> eq.not, \not\in -> in.not; without a named variant the relation gets a combining
+// long solidus (U+0338) overlay
+fn convert_not(relation: TypstNode) -> TypstNode {
+    if matches!(relation.node_type, TypstNodeType::Atom | TypstNodeType::Symbol) {
+        let name = match relation.content.as_str() {
+            "=" => "eq",
+            "<" => "lt",
+            ">" => "gt",
+            "tilde.op" => "tilde",
+            name => name,
+        };
+        let negated = format!("{}.not", name);
+        if NEGATED_SYMBOLS.contains(negated.as_str()) {
+            return TypstNode::new(TypstNodeType::Symbol, negated, None, None);
+        }
+    }
+    if relation.node_type == TypstNodeType::Atom {
+        return TypstNode::new(TypstNodeType::Atom, format!("{}\u{338}", relation.content), None, None);
+    }
+    // a symbol name would swallow the combining character, so it stands apart inside a relation class
+    let overlay = TypstNode::new(TypstNodeType::Atom, "\u{338}".to_string(), None, None);
+    TypstNode::new(
+        TypstNodeType::FuncCall,
+        "class".to_string(),
+        Some(vec![
+            TypstNode::new(TypstNodeType::Text, "relation".to_string(), None, None),
+            TypstNode::new(
+                TypstNodeType::Group,
+                "".to_string(),
+                Some(vec![relation, overlay]),
+                None,
+            ),
+        ]),
+        None,
+    )
+}
+
+// \mkern3mu -> thin, \hspace{2pt} -> #h(2pt), \mkern-3mu -> #h(-0.1667em)
+fn convert_spacing(length: &str) -> Result<TypstNode, String> {
+    let length = length.replace(' ', "");
+    // \fill and \stretch{2} take up the remaining space, like 1fr and 2fr
+    let factor = match length
+        .strip_prefix("\\stretch{")
+        .and_then(|rest| rest.strip_suffix('}'))
+    {
+        Some(factor) => Some(factor),
+        None if length == "\\fill" => Some("1"),
+        None => None,
+    };
+    if let Some(factor) = factor {
+        let factor = factor
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid length: {}", length))?;
+        return Ok(TypstNode::new(
+            TypstNodeType::Symbol,
+            format!("#h({}fr)", factor),
+            None,
+            None,
+        ));
+    }
+    let unit_start = length
+        .find(|c: char| c.is_ascii_alphabetic())
+        .ok_or(format!("Missing unit in length: {}", length))?;
+    let (value, unit) = length.split_at(unit_start);
+    // TeX requires a number before the unit, a lone sign as in \mkern-mu is not one
+    if !value.contains(|c: char| c.is_ascii_digit()) {
+        return Err(format!("Missing number in length: {}", length));
+    }
+    let value = value
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid length: {}", length))?;
+    // Typst has no math units, 18mu make up 1em
+    let (value, unit) = match unit {
+        "mu" => (value / 18.0, "em"),
+        "ex" => (value * 0.431, "em"),
+        "pc" => (value * 12.0, "pt"),
+        "bp" => (value * 72.27 / 72.0, "pt"),
+        "dd" => (value * 1238.0 / 1157.0, "pt"),
+        "cc" => (value * 12.0 * 1238.0 / 1157.0, "pt"),
+        "sp" => (value / 65536.0, "pt"),
+        "em" | "pt" | "mm" | "cm" | "in" => (value, unit),
+        _ => return Err(format!("Unknown unit in length: {}", length)),
+    };
+
+    let mu = value * 18.0;
+    let named_space = if unit == "em" && (mu - mu.round()).abs() < 1e-6 {
+        match mu.round() as i64 {
+            3 => Some("thin"),
+            4 => Some("med"),
+            5 => Some("thick"),
+            18 => Some("quad"),
+            36 => Some("wide"),
+            _ => None,
+        }
+    } else {
+        None
+    };
+    let content = match named_space {
+        Some(space) => space.to_string(),
+        None => {
+            let value = format!("{:.4}", value);
+            format!("#h({}{})", value.trim_end_matches('0').trim_end_matches('.'), unit)
+        }
+    };
+    Ok(TypstNode::new(TypstNodeType::Symbol, content, None, None))
+}
+
+fn zero_size_box(dimension: &str, body: TypstNode) -> TypstNode {
+    let mut res = TypstNode::new(TypstNodeType::FuncCall, "box".to_string(), Some(vec![body]), None);
+    res.set_options(HashMap::from([(dimension.to_string(), "#0pt".to_string())]));
+    res
+}
+
+// \mathllap and \mathclap align their content in a zero width box
+fn lap_alignment(command: &str) -> Option<&'static str> {
+    match command {
+        "\\mathllap" | "\\llap" => Some("right"),
+        "\\mathclap" | "\\clap" => Some("center"),
+        _ => None,
+    }
+}
+
+// \mathrel{\sim} -> class("relation", tilde.op)
+fn math_class(command: &str) -> Option<&'static str> {
+    match command {
+        "\\mathord" | "\\mathinner" => Some("normal"),
+        "\\mathbin" => Some("binary"),
+        "\\mathrel" => Some("relation"),
+        "\\mathopen" => Some("opening"),
+        "\\mathclose" => Some("closing"),
+        "\\mathpunct" => Some("punctuation"),
+        _ => None,
+    }
+}
+
+// xcolor names that have no Typst counterpart of the same name
+// Colors predefined in Typst, all of which except silver, navy, aqua, eastern, fuchsia and maroon are also xcolor names
+const TYPST_COLOR_NAMES: &[&str] = &[
+    "black", "gray", "silver", "white", "navy", "blue", "aqua", "teal", "eastern", "purple", "fuchsia", "maroon",
+    "red", "orange", "yellow", "olive", "green", "lime",
+];
+
+// The other xcolor base colors
+static XCOLOR_NAMES: phf::Map<&'static str, &'static str> = phf::phf_map! {
+    "cyan" => "rgb(\"#00ffff\")",
+    "magenta" => "rgb(\"#ff00ff\")",
+    "brown" => "rgb(\"#bf8040\")",
+    "darkgray" => "rgb(\"#404040\")",
+    "lightgray" => "rgb(\"#bfbfbf\")",
+    "pink" => "rgb(\"#ffbfbf\")",
+    "violet" => "rgb(\"#800080\")",
+};
+
+// An empty group such as the body of \textcolor{red}{}, which cannot be passed as an argument
+fn is_empty_body(node: &TypstNode) -> bool {
+    match node.node_type {
+        TypstNodeType::Empty => true,
+        TypstNodeType::Group => node.args.as_ref().is_none_or(|args| args.iter().all(is_empty_body)),
+        _ => false,
+    }
+}
+
+// Convert a color node produced by the parser to a Typst color expression usable as an argument in math mode
+// e.g. red -> red, red!30 -> #color.mix((red, 30%), (white, 70%)), [HTML]{FF0000} -> #rgb("#FF0000")
+fn convert_color(color: &TexNode) -> Result<String, String> {
+    let spec = color.content.as_str();
+    let expression = match color.args.as_ref().map(|args| args[0].content.as_str()) {
+        None => convert_xcolor_expression(spec)?,
+        Some(model) => {
+            let values = spec.split(',').map(|v| v.trim()).collect::<Vec<_>>();
+            let fractions = || -> Result<Vec<String>, String> {
+                values
+                    .iter()
+                    .map(|v| {
+                        v.parse::<f64>()
+                            .map(format_percent)
+                            .map_err(|_| format!("Invalid {} color value: {}", model, spec))
+                    })
+                    .collect()
+            };
+            match (model, values.len()) {
+                ("rgb", 3) => format!("rgb({})", fractions()?.join(", ")),
+                ("RGB", 3) => format!("rgb({})", values.join(", ")),
+                ("HTML", 1) if spec.len() == 6 && spec.chars().all(|c| c.is_ascii_hexdigit()) => {
+                    format!("rgb(\"#{}\")", spec)
+                }
+                ("gray", 1) => format!("luma({})", fractions()?[0]),
+                ("cmyk", 4) => format!("cmyk({})", fractions()?.join(", ")),
+                _ => return Err(format!("Unsupported color specification: [{}]{{{}}}", model, spec)),
+            }
+        }
+    };
+    if expression.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(expression)
+    } else {
+        Ok(format!("#{}", expression))
+    }
+}
+
+// red!50!blue -> color.mix((red, 50%), (blue, 50%)), a missing last color is white
+fn convert_xcolor_expression(spec: &str) -> Result<String, String> {
+    let parts = spec.split('!').map(|part| part.trim()).collect::<Vec<_>>();
+    let convert_name = |name: &str| -> Result<String, String> {
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(format!("Invalid color: {}", spec));
+        }
+        if let Some(color) = XCOLOR_NAMES.get(name) {
+            Ok(color.to_string())
+        } else if TYPST_COLOR_NAMES.contains(&name) {
+            Ok(name.to_string())
+        } else {
+            // dvipsnames, svgnames and user-defined colors have no Typst counterpart
+            Err(format!("Unknown color: {}", name))
+        }
+    };
+
+    let mut res = convert_name(parts[0])?;
+    for mix in parts[1..].chunks(2) {
+        let percent = mix[0]
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid percentage in color: {}", spec))?;
+        let other = match mix.get(1) {
+            Some(name) => convert_name(name)?,
+            None => "white".to_string(),
+        };
+        res = format!(
+            "color.mix(({}, {}), ({}, {}))",
+            res,
+            format_percent(percent / 100.0),
+            other,
+            format_percent(1.0 - percent / 100.0)
+        );
+    }
+    Ok(res)
+}
+
+// 0.5 -> 50%
+fn format_percent(fraction: f64) -> String {
+    let percent = format!("{:.2}", fraction * 100.0);
+    format!("{}%", percent.trim_end_matches('0').trim_end_matches('.'))
+}
+
+fn is_limits_modifier(node: &TexNode) -> bool {
+    node.node_type == TexNodeType::UnaryFunc && matches!(node.content.as_str(), "\\limits" | "\\nolimits")
+}
+
+// \sum\limits_a^b -> limits(sum)_a^b, \int\nolimits_a^b -> scripts(integral)_a^b
+// Without scripts the modifier has no visible effect, except for operators created by op()
+fn convert_limits_modifier(node: &TexNode, has_scripts: bool, options: &ConverterOptions) -> Result<TypstNode, String> {
+    let mut operator = convert_tree_with_options(&node.args.as_ref().unwrap()[0], options)?;
+    let limits = node.content == "\\limits";
+    if operator.node_type == TypstNodeType::FuncCall && operator.content == "op" {
+        // op() places scripts at the side unless asked otherwise
+        if limits {
+            operator.set_options(HashMap::from([("limits".to_string(), "#true".to_string())]));
+        }
+        return Ok(operator);
+    }
+    if !has_scripts {
+        return Ok(operator);
+    }
+    Ok(TypstNode::new(
+        TypstNodeType::FuncCall,
+        if limits { "limits" } else { "scripts" }.to_string(),
+        Some(vec![operator]),
+        None,
+    ))
+}
+
+fn convert_overset(node: &TexNode, options: &ConverterOptions) -> Result<TypstNode, String> {
     let args = node.args.as_ref().unwrap();
     let sup = &args[0];
     let base = &args[1];
@@ -373,7 +1565,7 @@ fn convert_overset(node: &TexNode) -> Result<TypstNode, String> {
     let mut op_call = TypstNode::new(
         TypstNodeType::FuncCall,
         "op".to_string(),
-        Some(vec![convert_tree(base)?]),
+        Some(vec![convert_tree_with_options(base, options)?]),
         None,
     );
     op_call.set_options(HashMap::from([("limits".to_string(), "true".to_string())]));
@@ -384,7 +1576,7 @@ fn convert_overset(node: &TexNode) -> Result<TypstNode, String> {
         None,
         Some(Box::from(TypstNodeData::Supsub(TypstSupsubData {
             base: op_call,
-            sup: Some(convert_tree(sup)?),
+            sup: Some(convert_tree_with_options(sup, options)?),
             sub: None,
         }))),
     ))
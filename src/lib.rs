@@ -1,4 +1,5 @@
 use crate::command_registry::{parse_custom_macros, CommandRegistry};
+use crate::converter::ConverterOptions;
 use crate::tex_parser::LatexParser;
 use crate::typst_writer::SymbolShorthand;
 use regex::{Captures, Regex};
@@ -6,7 +7,10 @@ use regex::{Captures, Regex};
 pub mod command_registry;
 pub mod converter;
 pub mod definitions;
+pub mod macro_packs;
 pub mod map;
+pub mod mhchem;
+pub mod siunitx;
 mod tests;
 pub mod tex_parser;
 pub mod tex_parser_utils;
@@ -42,8 +46,97 @@ pub mod typst_writer;
 /// println!("{}", typst_output);
 /// ```
 pub fn tex2typst(tex: &str) -> Result<String, String> {
-    let tex_tree = tex_parser::parse_tex(tex)?;
-    let typst_tree = converter::convert_tree(&tex_tree)?;
+    tex2typst_with_registry(tex, &CommandRegistry::new(), &ConverterOptions::default())
+}
+
+/// Converts a given TeX string to a Typst string with the given converter options.
+///
+/// This function works like `tex2typst`, but enables the opt-in conversions
+/// selected in `options`, such as the detection of differentials.
+///
+/// # Arguments
+///
+/// * `tex` - A string slice that holds the TeX input.
+/// * `options` - The options for the conversion of the TeX tree.
+///
+/// # Returns
+///
+/// * `Result<String, String>` - On success, returns the Typst string wrapped in `Ok`.
+///   On failure, returns an error message wrapped in `Err`.
+///
+/// # Errors
+///
+/// This function will return an error if the TeX parsing, conversion, or
+/// serialization fails.
+///
+/// # Example
+///
+/// ```
+/// use tex2typst_rs::converter::ConverterOptions;
+/// use tex2typst_rs::tex2typst_with_options;
+/// let options = ConverterOptions {
+///     detect_differentials: true,
+///     ..Default::default()
+/// };
+/// let typst_output = tex2typst_with_options(r"\int f(x) dx", &options).unwrap();
+/// println!("{}", typst_output);
+/// ```
+pub fn tex2typst_with_options(tex: &str, options: &ConverterOptions) -> Result<String, String> {
+    tex2typst_with_registry(tex, &CommandRegistry::new(), options)
+}
+
+/// Converts a given TeX string to a Typst string with the macros of a command registry
+/// and the given converter options.
+///
+/// This function tokenizes the TeX string, expands the macros registered in `registry`,
+/// parses the expanded tokens into a TeX tree, converts the TeX tree to a Typst tree with
+/// `options`, serializes the Typst tree, and returns the resulting Typst string.
+/// The other `tex2typst` functions are shorthands for it. Macro packs such as the
+/// physics package are enabled on the registry, e.g. with `register_physics_macros`.
+///
+/// # Arguments
+///
+/// * `tex` - A string slice that holds the TeX input.
+/// * `registry` - The command registry holding the custom macros to expand.
+/// * `options` - The options for the conversion of the TeX tree.
+///
+/// # Returns
+///
+/// * `Result<String, String>` - On success, returns the Typst string wrapped in `Ok`.
+///   On failure, returns an error message wrapped in `Err`.
+///
+/// # Errors
+///
+/// This function will return an error if the tokenization, macro expansion,
+/// TeX parsing, conversion, or serialization fails.
+///
+/// # Example
+///
+/// ```
+/// use tex2typst_rs::command_registry::{parse_custom_macros, CommandRegistry};
+/// use tex2typst_rs::converter::ConverterOptions;
+/// use tex2typst_rs::tex2typst_with_registry;
+/// let mut registry = CommandRegistry::new();
+/// registry.register_custom_macros(parse_custom_macros(r"\newcommand{\dd}{d}").unwrap());
+/// let options = ConverterOptions {
+///     detect_differentials: true,
+///     ..Default::default()
+/// };
+/// let typst_output = tex2typst_with_registry(r"\int f(x) \dd x", &registry, &options).unwrap();
+/// println!("{}", typst_output);
+/// ```
+pub fn tex2typst_with_registry(
+    tex: &str,
+    registry: &CommandRegistry,
+    options: &ConverterOptions,
+) -> Result<String, String> {
+    let tokens = tex_tokenizer::tokenize(tex)?;
+    let expanded_tokens = registry.expand_macros(&tokens)?;
+
+    let parser = LatexParser::new(false, false);
+    let tex_tree = parser.parse(expanded_tokens)?;
+    let typst_tree = converter::convert_tree_with_options(&tex_tree, options)?;
+
     let mut writer = typst_writer::TypstWriter::new();
     writer.serialize(&typst_tree)?;
     let typst = writer.finalize()?;
@@ -82,20 +175,10 @@ pub fn tex2typst(tex: &str) -> Result<String, String> {
 /// println!("{}", typst_output);
 /// ```
 pub fn tex2typst_with_macros(tex: &str, macro_definitions: &str) -> Result<String, String> {
-    let tokens = tex_tokenizer::tokenize(tex)?;
     let custom_macros = parse_custom_macros(macro_definitions)?;
     let mut registry = CommandRegistry::new();
     registry.register_custom_macros(custom_macros);
-    let expanded_tokens = registry.expand_macros(&tokens)?;
-
-    let parser = LatexParser::new(false, false);
-    let tex_tree = parser.parse(expanded_tokens)?;
-    let typst_tree = converter::convert_tree(&tex_tree)?;
-
-    let mut writer = typst_writer::TypstWriter::new();
-    writer.serialize(&typst_tree)?;
-    let typst = writer.finalize()?;
-    Ok(typst)
+    tex2typst_with_registry(tex, &registry, &ConverterOptions::default())
 }
 
 /// Converts a given input string containing TeX math expressions to Typst format.
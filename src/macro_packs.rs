@@ -0,0 +1,175 @@
+use crate::command_registry::{CommandType, CustomMacro};
+use crate::definitions::{TexToken, TexTokenType};
+use crate::tex_tokenizer::tokenize;
+
+type Arguments = Vec<Vec<TexToken>>;
+
+// The content of a variadic argument without its delimiters
+fn inner(argument: &[TexToken]) -> &[TexToken] {
+    &argument[1..argument.len() - 1]
+}
+
+// Replace #1, #2, ... in the template with the given token lists
+fn fill(template: &str, arguments: &[&[TexToken]]) -> Result<Vec<TexToken>, String> {
+    let mut res = Vec::new();
+    let mut rest = template;
+    while let Some(i) = rest.find('#') {
+        res.extend(tokenize(&rest[..i])?);
+        let argument = rest[i + 1..]
+            .chars()
+            .next()
+            .and_then(|c| c.to_digit(10))
+            .and_then(|index| arguments.get((index as usize).checked_sub(1)?))
+            .ok_or(format!("Invalid parameter in template: {}", template))?;
+        res.extend_from_slice(argument);
+        rest = &rest[i + 2..];
+    }
+    res.extend(tokenize(rest)?);
+    Ok(res)
+}
+
+fn symbol_macro(name: &str, definition: &'static str) -> CustomMacro {
+    CustomMacro {
+        name: name.to_string(),
+        command_type: CommandType::Symbol,
+        implementation: Box::new(move |_| tokenize(definition)),
+    }
+}
+
+// The implementation is told whether the command is starred, a size command such as \Big is dropped
+// since Typst sizes the delimiters to the content anyway
+fn variadic_macro(
+    name: &str,
+    max_args: usize,
+    implementation: impl Fn(bool, &[Vec<TexToken>]) -> Result<Vec<TexToken>, String> + 'static,
+) -> CustomMacro {
+    CustomMacro {
+        name: name.to_string(),
+        command_type: CommandType::Variadic(max_args),
+        implementation: Box::new(move |args: &Arguments| match args.first() {
+            Some(first) if first.len() == 1 && first[0].value == "*" => implementation(true, &args[1..]),
+            Some(first) if first.len() == 1 && first[0].token_type == TexTokenType::Command => {
+                implementation(false, &args[1..])
+            }
+            _ => implementation(false, args),
+        }),
+    }
+}
+
+// A macro taking exactly as many braced arguments as the template uses
+fn template_macro(name: &'static str, num_of_args: usize, template: &'static str) -> CustomMacro {
+    variadic_macro(name, num_of_args, move |_, args| {
+        if args.len() != num_of_args || args.iter().any(|arg| arg[0].value != "{") {
+            return Err(format!("Expecting {} braced arguments for {}", num_of_args, name));
+        }
+        fill(template, &args.iter().map(|arg| inner(arg)).collect::<Vec<_>>())
+    })
+}
+
+// \dv[n]{f}{x} -> \frac{\dif^{n} f}{\dif x^{n}}, \pdv{f}{x}{y} -> \frac{\partial^{2} f}{\partial x \partial y}
+// and the starred \dv*{f}{x} -> \dif f \slash \dif x
+fn derivative(name: &str, differential: &str, starred: bool, args: &[Vec<TexToken>]) -> Result<Vec<TexToken>, String> {
+    let (order, args) = match args.first() {
+        Some(first) if first[0].value == "[" => (Some(inner(first)), &args[1..]),
+        _ => (None, args),
+    };
+    let mut arguments: Vec<&[TexToken]> = args.iter().map(|arg| inner(arg)).collect();
+    let power = if let Some(order) = order {
+        arguments.push(order);
+        format!("^{{#{}}}", arguments.len())
+    } else {
+        String::new()
+    };
+    let (numerator, denominator) = match args.len() {
+        1 => (format!("{}{}", differential, power), format!("{} #1{}", differential, power)),
+        2 => (format!("{}{} #1", differential, power), format!("{} #2{}", differential, power)),
+        3 if differential == "\\partial" && order.is_some() => {
+            return Err(format!("An order is not supported for the mixed derivative {}", name))
+        }
+        3 if differential == "\\partial" => (format!("{d}^{{2}} #1", d = differential), format!("{d} #2 {d} #3", d = differential)),
+        _ => return Err(format!("Invalid arguments for {}", name)),
+    };
+    let template = if starred {
+        format!("{} \\slash {}", numerator, denominator)
+    } else {
+        format!("\\frac{{{}}}{{{}}}", numerator, denominator)
+    };
+    fill(&template, &arguments)
+}
+
+// The physics package, see https://ctan.org/pkg/physics
+pub fn physics_macros() -> Vec<CustomMacro> {
+    vec![
+        symbol_macro("\\grad", "\\nabla"),
+        symbol_macro("\\curl", "\\nabla \\times"),
+        symbol_macro("\\divergence", "\\nabla \\cdot"),
+        symbol_macro("\\laplacian", "\\nabla^{2}"),
+        symbol_macro("\\tr", "\\operatorname{tr}"),
+        symbol_macro("\\Tr", "\\operatorname{Tr}"),
+        symbol_macro("\\rank", "\\operatorname{rank}"),
+        template_macro("\\vb", 1, "\\mathbf{#1}"),
+        template_macro("\\va", 1, "\\vec{#1}"),
+        template_macro("\\vu", 1, "\\hat{\\mathbf{#1}}"),
+        template_macro("\\abs", 1, "\\left| #1 \\right|"),
+        template_macro("\\norm", 1, "\\left\\| #1 \\right\\|"),
+        template_macro("\\bra", 1, "\\left\\langle #1 \\right|"),
+        template_macro("\\ket", 1, "\\left| #1 \\right\\rangle"),
+        template_macro("\\mel", 3, "\\left\\langle #1 \\middle| #2 \\middle| #3 \\right\\rangle"),
+        // \braket{a} is the same as \braket{a}{a}
+        variadic_macro("\\braket", 2, |_, args| match args.len() {
+            1 => fill("\\left\\langle #1 \\middle| #1 \\right\\rangle", &[inner(&args[0])]),
+            2 => fill(
+                "\\left\\langle #1 \\middle| #2 \\right\\rangle",
+                &[inner(&args[0]), inner(&args[1])],
+            ),
+            _ => Err("Expecting one or two arguments for \\braket".to_string()),
+        }),
+        // \expval{A}{\psi} is the expectation value of A in the state psi
+        variadic_macro("\\expval", 2, |_, args| match args.len() {
+            1 => fill("\\left\\langle #1 \\right\\rangle", &[inner(&args[0])]),
+            2 => fill(
+                "\\left\\langle #2 \\middle| #1 \\middle| #2 \\right\\rangle",
+                &[inner(&args[0]), inner(&args[1])],
+            ),
+            _ => Err("Expecting one or two arguments for \\expval".to_string()),
+        }),
+        // \qty(x), \qty[x] and \qty{x} scale the delimiters to the content, while two braced arguments
+        // make the siunitx quantity \qty[options]{3}{\meter}, which is the same as \SI
+        variadic_macro("\\qty", 3, |_, args| {
+            let Some(arg) = args.first() else {
+                return Err("Expecting an argument for \\qty".to_string());
+            };
+            let braced = args.iter().filter(|arg| arg[0].value == "{").count();
+            if braced == 2 && (args.len() == 2 || args.len() == 3 && arg[0].value == "[") {
+                let mut res = tokenize("\\SI")?;
+                res.extend(args.iter().flatten().cloned());
+                return Ok(res);
+            }
+            let template = match arg[0].value.as_str() {
+                "(" => "\\left( #1 \\right)",
+                "[" => "\\left[ #1 \\right]",
+                _ => "\\left\\{ #1 \\right\\}",
+            };
+            // the arguments after the first one are not for \qty
+            let mut res = fill(template, &[inner(arg)])?;
+            res.extend(args[1..].iter().flatten().cloned());
+            Ok(res)
+        }),
+        variadic_macro("\\dv", 3, |starred, args| derivative("\\dv", "\\dif", starred, args)),
+        variadic_macro("\\pdv", 4, |starred, args| derivative("\\pdv", "\\partial", starred, args)),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fill() {
+        let x = tokenize("x").unwrap();
+        assert_eq!(fill(r"\abs{#1}", &[&x]).unwrap(), tokenize(r"\abs{x}").unwrap());
+        assert!(fill("a #", &[&x]).is_err());
+        assert!(fill("#0", &[&x]).is_err());
+        assert!(fill("#2", &[&x]).is_err());
+    }
+}
@@ -1,4 +1,4 @@
-use phf::phf_map;
+use phf::{phf_map, phf_set};
 
 pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "nonumber" => "",
@@ -12,6 +12,13 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "widetilde" => "tilde", // Ideally, the result of \widetilde should be longer than \tilde. But it is not implemented now.
         "quad" => "quad",
         "qquad" => "wide",
+        "enspace" => "space.en",
+        "thinspace" => "thin",
+        "medspace" => "med",
+        "thickspace" => "thick",
+        "negthinspace" => "#h(-0.1667em)",
+        "negmedspace" => "#h(-0.2222em)",
+        "negthickspace" => "#h(-0.2778em)",
         "overbrace" => "overbrace", // same
         "underbrace" => "underbrace", // same
         "overline" => "overline", // same
@@ -25,6 +32,7 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "operatorname" => "op",
 
         "boldsymbol" => "bold",
+        "bm" => "bold",
         "mathbb" => "bb",
         "mathbf" => "bold",
         "mathcal" => "cal",
@@ -35,6 +43,16 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "mathtt" => "mono",
 
         "rm" => "upright",
+        "bf" => "bold",
+        "it" => "italic",
+        "sf" => "sans",
+        "tt" => "mono",
+        "cal" => "cal",
+
+        "displaystyle" => "display",
+        "textstyle" => "inline",
+        "scriptstyle" => "script",
+        "scriptscriptstyle" => "sscript",
 
         "pmb" => "bold",
 
@@ -69,6 +87,11 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "bowtie" => "join",
         "bullet" => "bullet",
         "cdots" => "dots.c",
+        "dotsb" => "dots.c",
+        "dotsc" => "dots.h",
+        "dotsi" => "dots.c",
+        "dotsm" => "dots.c",
+        "dotso" => "dots.h",
         "chi" => "chi",
         "circ" => "circle.small", // 'circle.small' or 'compose'
         "colon" => "colon",
@@ -156,7 +179,11 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "obrbrak" => "shell.t",
         "ubrbrak" => "shell.b",
         "vert" => "bar.v",
+        "lvert" => "bar.v",
+        "rvert" => "bar.v",
         "Vert" => "bar.v.double",
+        "lVert" => "bar.v.double",
+        "rVert" => "bar.v.double",
         "Vvert" => "bar.v.triple",
         "circledvert" => "bar.v.circle",
         "horizbar" => "bar.h",
@@ -203,6 +230,11 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "Colon" => "colon.double",
         "coloneq" => "colon.eq",
         "Coloneq" => "colon.double.eq",
+        // \usepackage{mathtools}
+        "coloneqq" => "colon.eq",
+        "Coloneqq" => "colon.double.eq",
+        "eqqcolon" => "eq.colon",
+        "dblcolon" => "colon.double",
         "mathcomma" => "comma",
         "dagger" => "dagger",
         "ddagger" => "dagger.double",
@@ -346,6 +378,16 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "lnapprox" => "lt.napprox",
         "lneqq" => "lt.nequiv",
         "nless" => "lt.not",
+        "nleqslant" => "lt.eq.not",
+        "ngeqslant" => "gt.eq.not",
+        "nleqq" => "lt.eq.not",
+        "ngeqq" => "gt.eq.not",
+        "npreceq" => "prec.curly.eq.not",
+        "nsucceq" => "succ.curly.eq.not",
+        "nsubseteqq" => "subset.eq.not",
+        "nsupseteqq" => "supset.eq.not",
+        "nshortmid" => "divides.not",
+        "nshortparallel" => "parallel.not",
         "lnsim" => "lt.ntilde",
         "lesssim" => "lt.tilde",
         "nlesssim" => "lt.tilde.not",
@@ -450,6 +492,7 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
         "tieinfty" => "infinity.tie",
         "partial" => "diff",
         "nabla" => "gradient",
+        "dif" => "dif",
         "sum" => "sum",
         "sumint" => "sum.integral",
         "prod" => "product",
@@ -888,3 +931,50 @@ pub static SYMBOL_MAP: phf::Map<&'static str, &'static str> = phf_map! {
 
         "hyph.minus" => "\\text{-}",
 };
+
+// Typst symbols with a negated .not variant, used by \not
+pub static NEGATED_SYMBOLS: phf::Set<&'static str> = phf_set! {
+        "approx.not",
+        "arrow.l.double.not",
+        "arrow.l.not",
+        "arrow.l.r.double.not",
+        "arrow.l.r.not",
+        "arrow.r.double.not",
+        "arrow.r.not",
+        "backslash.not",
+        "divides.not",
+        "eq.not",
+        "equiv.not",
+        "exists.not",
+        "forces.not",
+        "gt.eq.not",
+        "gt.lt.not",
+        "gt.not",
+        "gt.tilde.not",
+        "gt.tri.eq.not",
+        "gt.tri.not",
+        "in.not",
+        "in.rev.not",
+        "lt.eq.not",
+        "lt.gt.not",
+        "lt.not",
+        "lt.tilde.not",
+        "lt.tri.eq.not",
+        "lt.tri.not",
+        "parallel.not",
+        "prec.curly.eq.not",
+        "prec.not",
+        "subset.eq.not",
+        "subset.eq.sq.not",
+        "subset.not",
+        "succ.curly.eq.not",
+        "succ.not",
+        "supset.eq.not",
+        "supset.eq.sq.not",
+        "supset.not",
+        "tack.r.double.not",
+        "tack.r.not",
+        "tilde.eq.not",
+        "tilde.equiv.not",
+        "tilde.not",
+};
@@ -0,0 +1,296 @@
+use crate::definitions::{TypstNode, TypstNodeType};
+use crate::siunitx::{format_number, format_unit_spec};
+use crate::tex2typst;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum MhchemTarget {
+    // plain Typst math, e.g. "H"_2"O" arrow.r "H"^(+) + "OH"^(-)
+    #[default]
+    Math,
+    // the typsium package, e.g. #ce("H2O -> H+ + OH-")
+    Typsium,
+}
+
+// Longer arrows first so that <=>> is not read as <=>
+const ARROWS: &[(&str, &str)] = &[
+    ("<=>>", "harpoons.rtlb"),
+    ("<<=>", "harpoons.rtlb"),
+    ("<=>", "harpoons.rtlb"),
+    ("<-->", "arrows.rl"),
+    ("<->", "arrow.l.r"),
+    ("->", "arrow.r"),
+    ("<-", "arrow.l"),
+];
+
+// (s), (l), (g) and (aq) after a formula
+const STATES: &[&str] = &["s", "l", "g", "aq", "cr"];
+
+// Read the text up to the bracket closing the one at start, nested brackets included
+fn read_group(chars: &[char], start: usize, left: char, right: char) -> Result<(String, usize), String> {
+    let mut depth = 0;
+    for (i, c) in chars.iter().enumerate().skip(start) {
+        if *c == left {
+            depth += 1;
+        } else if *c == right {
+            depth -= 1;
+            if depth == 0 {
+                return Ok((chars[start + 1..i].iter().collect(), i + 1));
+            }
+        }
+    }
+    Err(format!("Unmatched '{}' in \\ce", left))
+}
+
+// x -> x, "max" -> "max", a b -> (a b)
+fn attachment(content: &str) -> String {
+    let is_text = content.len() >= 2
+        && content.starts_with('"')
+        && content.ends_with('"')
+        && content.matches('"').count() == 2;
+    if is_text || content.chars().all(|c| c.is_alphanumeric()) {
+        content.to_string()
+    } else {
+        format!("({})", content)
+    }
+}
+
+// 2-, 3+, + -> ^(2-), ^(3+), ^(+) and oxidation states such as II -> ^"II"
+fn charge(charge: &str) -> String {
+    if charge.chars().all(|c| c.is_ascii_digit() || c == '+' || c == '-') {
+        format!("^{}", attachment(charge))
+    } else {
+        format!("^\"{}\"", charge)
+    }
+}
+
+// 2, 1/2 or 5 before a formula
+fn read_coefficient(chars: &[char], start: usize) -> (String, usize) {
+    let coefficient: String = chars[start..]
+        .iter()
+        .take_while(|c| c.is_ascii_digit() || **c == '/')
+        .collect();
+    let end = start + coefficient.len();
+    (coefficient, end)
+}
+
+// H2SO4 -> "H"_2"SO"_4, SO4^2- -> "SO"_4^(2-), Ca(OH)2 -> "Ca"("OH")_2, NaCl(aq) -> "NaCl""(aq)"
+fn convert_formula(formula: &str) -> Result<String, String> {
+    let chars: Vec<char> = formula.chars().collect();
+    let mut res = String::new();
+    // consecutive letters are written as one upright text
+    let mut text = String::new();
+    let flush = |res: &mut String, text: &mut String| {
+        if !text.is_empty() {
+            res.push_str(&format!("\"{}\"", text));
+            text.clear();
+        }
+    };
+
+    let (coefficient, mut pos) = read_coefficient(&chars, 0);
+    if !coefficient.is_empty() {
+        res.push_str(&coefficient);
+        if pos < chars.len() {
+            res.push(' ');
+        }
+    }
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c.is_alphabetic() {
+            text.push(c);
+            pos += 1;
+        } else if c.is_ascii_digit() {
+            flush(&mut res, &mut text);
+            let digits: String = chars[pos..].iter().take_while(|c| c.is_ascii_digit()).collect();
+            pos += digits.len();
+            res.push_str(&format!("_{}", digits));
+        } else if c == '(' || c == '[' {
+            flush(&mut res, &mut text);
+            let right = if c == '(' { ')' } else { ']' };
+            let (group, new_pos) = read_group(&chars, pos, c, right)?;
+            pos = new_pos;
+            if c == '(' && STATES.contains(&group.as_str()) {
+                res.push_str(&format!("\"({})\"", group));
+            } else {
+                res.push_str(&format!("{}{}{}", c, convert_formula(&group)?, right));
+            }
+        } else if c == '^' && pos + 1 == chars.len() {
+            // the tokenizer drops the space in CO2 ^, so a trailing ^ is the gas arrow
+            flush(&mut res, &mut text);
+            pos += 1;
+            res.push_str(" arrow.t");
+        } else if c == '^' {
+            flush(&mut res, &mut text);
+            pos += 1;
+            let content = if chars.get(pos) == Some(&'{') {
+                let (group, new_pos) = read_group(&chars, pos, '{', '}')?;
+                pos = new_pos;
+                group
+            } else {
+                let digits = chars[pos..].iter().take_while(|c| c.is_ascii_digit()).count();
+                let signs = chars[pos + digits..]
+                    .iter()
+                    .take_while(|c| **c == '+' || **c == '-')
+                    .count();
+                let content: String = chars[pos..pos + digits + signs].iter().collect();
+                pos += content.chars().count();
+                content
+            };
+            if content.is_empty() {
+                return Err(format!("Expecting a charge after ^ in {}", formula));
+            }
+            res.push_str(&charge(&content));
+        } else if (c == '+' || c == '-') && chars[pos..].iter().all(|c| *c == '+' || *c == '-') {
+            // trailing signs are the charge, e.g. OH- or e-
+            flush(&mut res, &mut text);
+            let signs: String = chars[pos..].iter().collect();
+            pos = chars.len();
+            res.push_str(&charge(&signs));
+        } else if c == '.' || c == '*' {
+            // hydrates, e.g. CuSO4.5H2O
+            flush(&mut res, &mut text);
+            let (coefficient, new_pos) = read_coefficient(&chars, pos + 1);
+            pos = new_pos;
+            res.push_str(" dot ");
+            if !coefficient.is_empty() {
+                res.push_str(&format!("{} ", coefficient));
+            }
+        } else if c == '-' || c == '=' || c == '#' {
+            // single, double and triple bonds
+            flush(&mut res, &mut text);
+            pos += 1;
+            res.push_str(match c {
+                '-' => "-",
+                '=' => "=",
+                _ => "equiv",
+            });
+        } else {
+            return Err(format!("Unexpected character in \\ce: {}", c));
+        }
+    }
+    flush(&mut res, &mut text);
+    Ok(res)
+}
+
+// A label above or below an arrow, {text} is kept as text, $x$ and labels with TeX commands such as \Delta
+// are TeX math, and anything else is a formula
+fn convert_label(label: &str) -> Result<String, String> {
+    let label = label.trim();
+    if let Some(text) = label.strip_prefix('{').and_then(|label| label.strip_suffix('}')) {
+        Ok(format!("\"{}\"", text))
+    } else if let Some(math) = label.strip_prefix('$').and_then(|label| label.strip_suffix('$')) {
+        tex2typst(math)
+    } else if label.contains('\\') {
+        tex2typst(label)
+    } else {
+        convert_equation(label)
+    }
+}
+
+fn find_arrow(chars: &[char]) -> Option<(&'static str, &'static str)> {
+    ARROWS
+        .iter()
+        .copied()
+        .find(|(arrow, _)| chars.iter().take(arrow.len()).copied().eq(arrow.chars()))
+}
+
+// H2SO4 -> 2H+ + SO4^2- -> "H"_2"SO"_4 arrow.r 2 "H"^(+) + "SO"_4^(2-)
+fn convert_equation(equation: &str) -> Result<String, String> {
+    let chars: Vec<char> = equation.chars().collect();
+    let mut parts: Vec<String> = Vec::new();
+    let mut pos = 0;
+    while pos < chars.len() {
+        if chars[pos].is_whitespace() {
+            pos += 1;
+            continue;
+        }
+        if let Some((arrow, symbol)) = find_arrow(&chars[pos..]) {
+            pos += arrow.len();
+            // ->[above][below]
+            let mut labels = Vec::new();
+            while labels.len() < 2 && chars.get(pos) == Some(&'[') {
+                let (label, new_pos) = read_group(&chars, pos, '[', ']')?;
+                pos = new_pos;
+                labels.push(convert_label(&label)?);
+            }
+            let mut arrow = symbol.to_string();
+            if !labels.is_empty() {
+                arrow = format!("stretch({})", arrow);
+                for (label, script) in labels.iter().zip(["^", "_"]) {
+                    if !label.is_empty() {
+                        arrow.push_str(&format!("{}{}", script, attachment(label)));
+                    }
+                }
+            }
+            parts.push(arrow);
+            continue;
+        }
+
+        // a word goes up to the next whitespace or arrow outside of brackets, as in A->B
+        let mut end = pos;
+        let mut depth = 0;
+        while end < chars.len() && (depth > 0 || !(chars[end].is_whitespace() || find_arrow(&chars[end..]).is_some())) {
+            match chars[end] {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth -= 1,
+                _ => {}
+            }
+            end += 1;
+        }
+        let word: String = chars[pos..end].iter().collect();
+        pos = end;
+        parts.push(match word.as_str() {
+            "+" => "+".to_string(),
+            // precipitate and gas
+            "v" => "arrow.b".to_string(),
+            "^" => "arrow.t".to_string(),
+            _ => convert_formula(&word)?,
+        });
+    }
+    Ok(parts.join(" "))
+}
+
+// \pu{123 kJ mol-1} -> 123 thin "kJ" dot "mol"^(-1)
+fn convert_physical_unit(quantity: &str) -> Result<String, String> {
+    let quantity = quantity.trim();
+    let (number, unit) = match quantity.split_once(char::is_whitespace) {
+        Some((number, unit)) => (number, unit.trim()),
+        None if quantity.starts_with(|c: char| c.is_ascii_digit() || c == '.' || c == '-') => (quantity, ""),
+        None => ("", quantity),
+    };
+    // mhchem writes powers without ^, e.g. mol-1 or m2
+    let unit = unit
+        .split_whitespace()
+        .map(|part| {
+            let power_start = part
+                .rfind(|c: char| !(c.is_ascii_digit() || c == '-'))
+                .map_or(0, |i| i + 1);
+            if power_start > 0 && power_start < part.len() {
+                format!("{}^{}", &part[..power_start], &part[power_start..])
+            } else {
+                part.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(".");
+    match (number.is_empty(), unit.is_empty()) {
+        (true, _) => format_unit_spec(&unit),
+        (false, true) => format_number(number),
+        (false, false) => Ok(format!("{} thin {}", format_number(number)?, format_unit_spec(&unit)?)),
+    }
+}
+
+// \ce{...} and \pu{...}, the argument is the raw text
+pub fn convert_mhchem(command: &str, text: &str, target: MhchemTarget) -> Result<TypstNode, String> {
+    if text.trim().is_empty() {
+        return Err(format!("Empty argument for {}", command));
+    }
+    let res = match (target, command) {
+        (MhchemTarget::Math, "\\ce") => convert_equation(text)?,
+        (MhchemTarget::Typsium, "\\ce") => format!("#ce(\"{}\")", text.replace('\\', "\\\\").replace('"', "\\\"")),
+        // typsium has no counterpart of \pu
+        (_, "\\pu") => convert_physical_unit(text)?,
+        _ => return Err(format!("Unknown mhchem command: {}", command)),
+    };
+    Ok(TypstNode::new(TypstNodeType::Symbol, res, None, None))
+}
@@ -0,0 +1,465 @@
+use crate::definitions::{TypstNode, TypstNodeType};
+use phf::phf_map;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SiunitxTarget {
+    // plain Typst math, e.g. 9.81 thin "m" dot "s"^(-2)
+    #[default]
+    Math,
+    // the unify package, e.g. #qty("9.81", "meter per second squared")
+    Unify,
+}
+
+static PREFIXES: phf::Map<&'static str, &'static str> = phf_map! {
+    "yocto" => "y",
+    "zepto" => "z",
+    "atto" => "a",
+    "femto" => "f",
+    "pico" => "p",
+    "nano" => "n",
+    "micro" => "µ",
+    "milli" => "m",
+    "centi" => "c",
+    "deci" => "d",
+    "deca" => "da",
+    "deka" => "da",
+    "hecto" => "h",
+    "kilo" => "k",
+    "mega" => "M",
+    "giga" => "G",
+    "tera" => "T",
+    "peta" => "P",
+    "exa" => "E",
+    "zetta" => "Z",
+    "yotta" => "Y",
+};
+
+static UNITS: phf::Map<&'static str, &'static str> = phf_map! {
+    "ampere" => "A",
+    "candela" => "cd",
+    "kelvin" => "K",
+    "kilogram" => "kg",
+    "gram" => "g",
+    "meter" => "m",
+    "metre" => "m",
+    "mole" => "mol",
+    "second" => "s",
+    "becquerel" => "Bq",
+    "coulomb" => "C",
+    "degreeCelsius" => "°C",
+    "farad" => "F",
+    "gray" => "Gy",
+    "hertz" => "Hz",
+    "henry" => "H",
+    "joule" => "J",
+    "lumen" => "lm",
+    "katal" => "kat",
+    "lux" => "lx",
+    "newton" => "N",
+    "ohm" => "Ω",
+    "pascal" => "Pa",
+    "radian" => "rad",
+    "siemens" => "S",
+    "sievert" => "Sv",
+    "steradian" => "sr",
+    "tesla" => "T",
+    "volt" => "V",
+    "watt" => "W",
+    "weber" => "Wb",
+    "bar" => "bar",
+    "day" => "d",
+    "degree" => "°",
+    "arcminute" => "′",
+    "arcsecond" => "″",
+    "electronvolt" => "eV",
+    "hectare" => "ha",
+    "hour" => "h",
+    "liter" => "L",
+    "litre" => "L",
+    "minute" => "min",
+    "percent" => "%",
+    "tonne" => "t",
+    "angstrom" => "Å",
+    "atomicmassunit" => "u",
+    "bel" => "B",
+    "decibel" => "dB",
+    "dalton" => "Da",
+};
+
+// The abbreviated units of siunitx, e.g. \km or \GHz
+static ABBREVIATIONS: phf::Map<&'static str, &'static str> = phf_map! {
+    "fg" => "fg",
+    "pg" => "pg",
+    "ng" => "ng",
+    "ug" => "µg",
+    "mg" => "mg",
+    "g" => "g",
+    "kg" => "kg",
+    "pm" => "pm",
+    "nm" => "nm",
+    "um" => "µm",
+    "mm" => "mm",
+    "cm" => "cm",
+    "dm" => "dm",
+    "m" => "m",
+    "km" => "km",
+    "as" => "as",
+    "fs" => "fs",
+    "ps" => "ps",
+    "ns" => "ns",
+    "us" => "µs",
+    "ms" => "ms",
+    "s" => "s",
+    "fmol" => "fmol",
+    "pmol" => "pmol",
+    "nmol" => "nmol",
+    "umol" => "µmol",
+    "mmol" => "mmol",
+    "mol" => "mol",
+    "kmol" => "kmol",
+    "pA" => "pA",
+    "nA" => "nA",
+    "uA" => "µA",
+    "mA" => "mA",
+    "A" => "A",
+    "kA" => "kA",
+    "ul" => "µl",
+    "ml" => "ml",
+    "l" => "l",
+    "hl" => "hl",
+    "uL" => "µL",
+    "mL" => "mL",
+    "L" => "L",
+    "hL" => "hL",
+    "mHz" => "mHz",
+    "Hz" => "Hz",
+    "kHz" => "kHz",
+    "MHz" => "MHz",
+    "GHz" => "GHz",
+    "THz" => "THz",
+    "mN" => "mN",
+    "N" => "N",
+    "kN" => "kN",
+    "MN" => "MN",
+    "Pa" => "Pa",
+    "kPa" => "kPa",
+    "MPa" => "MPa",
+    "GPa" => "GPa",
+    "mohm" => "mΩ",
+    "kohm" => "kΩ",
+    "Mohm" => "MΩ",
+    "pV" => "pV",
+    "nV" => "nV",
+    "uV" => "µV",
+    "mV" => "mV",
+    "V" => "V",
+    "kV" => "kV",
+    "W" => "W",
+    "nW" => "nW",
+    "uW" => "µW",
+    "mW" => "mW",
+    "kW" => "kW",
+    "MW" => "MW",
+    "GW" => "GW",
+    "J" => "J",
+    "uJ" => "µJ",
+    "mJ" => "mJ",
+    "kJ" => "kJ",
+    "eV" => "eV",
+    "meV" => "meV",
+    "keV" => "keV",
+    "MeV" => "MeV",
+    "GeV" => "GeV",
+    "TeV" => "TeV",
+    "kWh" => "kWh",
+    "F" => "F",
+    "fF" => "fF",
+    "pF" => "pF",
+    "nF" => "nF",
+    "uF" => "µF",
+    "H" => "H",
+    "mH" => "mH",
+    "C" => "C",
+    "nC" => "nC",
+    "mC" => "mC",
+    "uC" => "µC",
+    "K" => "K",
+    "dB" => "dB",
+};
+
+// Angles are written right after the number, without a space
+const ANGLE_UNITS: &[&str] = &["°", "′", "″"];
+
+struct Unit {
+    symbol: String,
+    power: i32,
+}
+
+fn read_braced(chars: &[char], start: usize) -> Result<(String, usize), String> {
+    if chars.get(start) != Some(&'{') {
+        return Err("Expecting { in the unit".to_string());
+    }
+    let end = chars[start..]
+        .iter()
+        .position(|c| *c == '}')
+        .ok_or("Unmatched '{' in the unit")?;
+    Ok((chars[start + 1..start + end].iter().collect(), start + end + 1))
+}
+
+fn parse_power(power: &str) -> Result<i32, String> {
+    power
+        .trim()
+        .parse::<i32>()
+        .map_err(|_| format!("Invalid power in the unit: {}", power))
+}
+
+// \kilo\meter\per\second\squared or the literal kg.m/s^2 -> kg, m, s^-2
+fn parse_units(spec: &str) -> Result<Vec<Unit>, String> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut units: Vec<Unit> = Vec::new();
+    let mut prefix = String::new();
+    let mut pending_power = 1;
+    let mut per = false;
+    let mut pos = 0;
+
+    let push = |units: &mut Vec<Unit>, symbol: String, pending_power: &mut i32, per: &mut bool| {
+        let sign = if *per { -1 } else { 1 };
+        units.push(Unit {
+            symbol,
+            power: *pending_power * sign,
+        });
+        *pending_power = 1;
+        *per = false;
+    };
+
+    while pos < chars.len() {
+        let c = chars[pos];
+        if c == '\\' {
+            let name: String = chars[pos + 1..]
+                .iter()
+                .take_while(|c| c.is_ascii_alphabetic())
+                .collect();
+            pos += 1 + name.len();
+            if let Some(symbol) = PREFIXES.get(name.as_str()) {
+                prefix.push_str(symbol);
+            } else if let Some(symbol) = UNITS.get(name.as_str()).or(ABBREVIATIONS.get(name.as_str())) {
+                push(
+                    &mut units,
+                    format!("{}{}", prefix, symbol),
+                    &mut pending_power,
+                    &mut per,
+                );
+                prefix.clear();
+            } else {
+                match name.as_str() {
+                    "per" => per = true,
+                    "square" => pending_power = 2,
+                    "cubic" => pending_power = 3,
+                    "squared" | "cubed" | "tothe" => {
+                        let power = match name.as_str() {
+                            "squared" => 2,
+                            "cubed" => 3,
+                            _ => {
+                                let (power, new_pos) = read_braced(&chars, pos)?;
+                                pos = new_pos;
+                                parse_power(&power)?
+                            }
+                        };
+                        units.last_mut().ok_or(format!("No unit before \\{}", name))?.power *= power;
+                    }
+                    "raiseto" => {
+                        let (power, new_pos) = read_braced(&chars, pos)?;
+                        pos = new_pos;
+                        pending_power = parse_power(&power)?;
+                    }
+                    _ => return Err(format!("Unknown unit: \\{}", name)),
+                }
+            }
+        } else if c.is_alphabetic() || ['%', '°', 'Ω', 'µ'].contains(&c) {
+            let symbol: String = chars[pos..]
+                .iter()
+                .take_while(|c| c.is_alphabetic() || ['%', '°', 'Ω', 'µ'].contains(c))
+                .collect();
+            pos += symbol.chars().count();
+            push(&mut units, symbol, &mut pending_power, &mut per);
+        } else if c == '^' {
+            pos += 1;
+            let power = if chars.get(pos) == Some(&'{') {
+                let (power, new_pos) = read_braced(&chars, pos)?;
+                pos = new_pos;
+                power
+            } else {
+                let power: String = chars[pos..]
+                    .iter()
+                    .enumerate()
+                    .take_while(|(i, c)| c.is_ascii_digit() || (*i == 0 && **c == '-'))
+                    .map(|(_, c)| c)
+                    .collect();
+                pos += power.len();
+                power
+            };
+            units.last_mut().ok_or("No unit before ^")?.power *= parse_power(&power)?;
+        } else if c == '/' {
+            per = true;
+            pos += 1;
+        } else if [' ', '.', '~', '*'].contains(&c) {
+            pos += 1;
+        } else {
+            return Err(format!("Unexpected character in the unit: {}", c));
+        }
+    }
+    if units.is_empty() {
+        return Err("Missing unit".to_string());
+    }
+    Ok(units)
+}
+
+fn format_units(units: &[Unit]) -> String {
+    units
+        .iter()
+        .map(|unit| match unit.power {
+            1 => format!("\"{}\"", unit.symbol),
+            power if power > 0 => format!("\"{}\"^{}", unit.symbol, power),
+            power => format!("\"{}\"^({})", unit.symbol, power),
+        })
+        .collect::<Vec<_>>()
+        .join(" dot ")
+}
+
+// \kilo\joule\per\mole or kJ/mol -> "kJ" dot "mol"^(-1)
+pub(crate) fn format_unit_spec(spec: &str) -> Result<String, String> {
+    Ok(format_units(&parse_units(spec)?))
+}
+
+// Separate groups of three digits with thin spaces once there are at least five digits
+fn group_digits(digits: &str, from_left: bool) -> String {
+    if digits.len() < 5 {
+        return digits.to_string();
+    }
+    let chars: Vec<char> = digits.chars().collect();
+    let groups: Vec<String> = if from_left {
+        chars.chunks(3).map(|group| group.iter().collect()).collect()
+    } else {
+        let mut groups: Vec<String> = chars.rchunks(3).map(|group| group.iter().collect()).collect();
+        groups.reverse();
+        groups
+    };
+    groups.join(" thin ")
+}
+
+fn format_decimal(decimal: &str) -> Result<String, String> {
+    let (sign, digits) = match decimal.strip_prefix(['-', '+']) {
+        Some(digits) => (&decimal[..1], digits),
+        None => ("", decimal),
+    };
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if !integer.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err(format!("Invalid number: {}", decimal));
+    }
+    if integer.is_empty() && fraction.is_empty() {
+        return Err("Missing number".to_string());
+    }
+    let integer = if integer.is_empty() { "0" } else { integer };
+    let mut res = format!("{}{}", sign, group_digits(integer, false));
+    if !fraction.is_empty() {
+        res.push('.');
+        res.push_str(&group_digits(fraction, true));
+    }
+    Ok(res)
+}
+
+// 1.2e-3 -> 1.2 times 10^(-3), 12345.6 -> 12 thin 345.6, 1.2 +- 0.1 -> 1.2 plus.minus 0.1
+pub(crate) fn format_number(number: &str) -> Result<String, String> {
+    let number: String = number
+        .replace("\\pm", "+-")
+        .replace("\\times", "x")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == ',' { '.' } else { c })
+        .collect();
+    if let Some((left, right)) = number.split_once('x') {
+        return Ok(format!("{} times {}", format_number(left)?, format_number(right)?));
+    }
+    if let Some((value, uncertainty)) = number.split_once("+-") {
+        return Ok(format!(
+            "{} plus.minus {}",
+            format_number(value)?,
+            format_number(uncertainty)?
+        ));
+    }
+    let Some(exponent_pos) = number.find(['e', 'E', 'd', 'D']) else {
+        return format_decimal(&number);
+    };
+    let exponent = number[exponent_pos + 1..].trim_start_matches('+');
+    if exponent.parse::<i32>().is_err() {
+        return Err(format!("Invalid exponent in number: {}", number));
+    }
+    let power = if exponent.starts_with('-') {
+        format!("10^({})", exponent)
+    } else {
+        format!("10^{}", exponent)
+    };
+    let mantissa = &number[..exponent_pos];
+    if mantissa.is_empty() {
+        return Ok(power);
+    }
+    Ok(format!("{} times {}", format_decimal(mantissa)?, power))
+}
+
+// \ang{1;2;3} -> 1 "°" 2 "′" 3 "″", empty parts are left out
+fn format_angle(angle: &str) -> Result<String, String> {
+    let parts: Vec<&str> = angle.split(';').collect();
+    if parts.len() > 3 {
+        return Err(format!("Invalid angle: {}", angle));
+    }
+    let mut res = Vec::new();
+    for (part, unit) in parts.iter().zip(ANGLE_UNITS) {
+        if !part.trim().is_empty() {
+            res.push(format!("{} \"{}\"", format_number(part)?, unit));
+        }
+    }
+    if res.is_empty() {
+        return Err(format!("Missing angle: {}", angle));
+    }
+    Ok(res.join(" "))
+}
+
+fn format_quantity(number: &str, unit: &str) -> Result<String, String> {
+    let units = parse_units(unit)?;
+    let separator = match units.as_slice() {
+        [unit] if unit.power == 1 && ANGLE_UNITS.contains(&unit.symbol.as_str()) => " ",
+        _ => " thin ",
+    };
+    Ok(format!(
+        "{}{}{}",
+        format_number(number)?,
+        separator,
+        format_units(&units)
+    ))
+}
+
+// \kilo\meter\per\second\squared -> kilo meter per second squared, literal units are kept as they are
+fn unify_unit(unit: &str) -> String {
+    unit.replace('\\', " ").trim().to_string()
+}
+
+// \SI{9.81}{\meter\per\second\squared} and the other siunitx commands, the arguments are the raw texts
+pub fn convert_siunitx(command: &str, args: &[String], target: SiunitxTarget) -> Result<TypstNode, String> {
+    if args.iter().any(|arg| arg.trim().is_empty()) {
+        return Err(format!("Empty argument for {}", command));
+    }
+    let res = match (target, command) {
+        (SiunitxTarget::Math, "\\SI" | "\\qty") => format_quantity(&args[0], &args[1])?,
+        (SiunitxTarget::Math, "\\si" | "\\unit") => format_unit_spec(&args[0])?,
+        (SiunitxTarget::Math, "\\num") => format_number(&args[0])?,
+        (SiunitxTarget::Math, "\\ang") => format_angle(&args[0])?,
+        (SiunitxTarget::Unify, "\\SI" | "\\qty") => {
+            format!("#qty(\"{}\", \"{}\")", args[0], unify_unit(&args[1]))
+        }
+        (SiunitxTarget::Unify, "\\si" | "\\unit") => format!("#unit(\"{}\")", unify_unit(&args[0])),
+        (SiunitxTarget::Unify, "\\num") => format!("#num(\"{}\")", args[0]),
+        (SiunitxTarget::Unify, "\\ang") => format!("#qty(\"{}\", \"degree\")", args[0]),
+        _ => return Err(format!("Unknown siunitx command: {}", command)),
+    };
+    Ok(TypstNode::new(TypstNodeType::Symbol, res, None, None))
+}
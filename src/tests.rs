@@ -109,7 +109,7 @@ mod tests {
 
 #[cfg(test)]
 mod test_custom_macros {
-    use crate::{tex2typst_with_macros, tex_parser};
+    use crate::{tex2typst, tex2typst_with_macros, tex_parser};
     use std::collections::HashMap;
 
     #[test]
@@ -128,6 +128,16 @@ mod test_custom_macros {
         assert_eq!(result, "(diff f)/(diff x) diff/(diff y)");
     }
 
+    #[test]
+    fn test_declare_math_operator() {
+        let custom_macros = r"\DeclareMathOperator{\Tr}{Tr}
+        \DeclareMathOperator*{\argmax}{arg\,max}
+        \DeclareMathOperator{\Det}{det}";
+        let tex = r"\Tr A + \argmax_{x} f(x) + \Det B";
+        let result = tex2typst_with_macros(tex, custom_macros).unwrap();
+        assert_eq!(result, "op(\"Tr\") A + op(\"arg max\", limits: #true)_x f(x) + det B");
+    }
+
     #[test]
     fn test_convoluted_square_brackets() {
         let custom_macros = r"\newcommand{\pp}[2][]{\frac{\partial #1}{\partial #2}}";
@@ -135,6 +145,72 @@ mod test_custom_macros {
         let result = tex2typst_with_macros(tex, custom_macros).unwrap();
         assert_eq!(result, "(diff f [x])/(diff y)");
     }
+
+    #[test]
+    fn test_custom_macros_with_many_args() {
+        let custom_macros = r"\newcommand{\inner}[3]{\langle #1, #2 \rangle_{#3}}
+        \newcommand{\tensor}[6][T]{#1^{#2 #3}_{#4 #5 #6}}
+        \newcommand{\twice}[1]{#1 + #1}
+        \newcommand{\hash}{\##}
+        \newcommand{\group}[1]{{#1}}";
+        let test_list = vec![
+            (r"\inner{a}{b}{c}", "angle.l a, b angle.r_c"),
+            (r"\tensor{i}{j}{k}{l}{m}", "T_(k l m)^(i j)"),
+            (r"\tensor[R]{i}{j}{k}{l}{m}", "R_(k l m)^(i j)"),
+            (r"\twice{\alpha}", "alpha + alpha"),
+            (r"\hash", "\\#"),
+            (r"\group{x}^2", "x^2"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_macros(tex, custom_macros).unwrap(), typst);
+        }
+        assert!(tex2typst_with_macros(r"\inner{a}{b}", custom_macros).is_err());
+        assert!(tex2typst_with_macros(r"x", r"\newcommand{\bad}[1]{#2}").is_err());
+    }
+
+    #[test]
+    fn test_definition_forms() {
+        let custom_macros = r"\newcommand*{\N}{\mathbb{N}}
+        \newcommand\Z{\mathbb{Z}}
+        \renewcommand{\Z}{\mathbb{Z}^{+}}
+        \providecommand{\N}{X}
+        \providecommand{\alpha}{X}
+        \providecommand{\Q}{\mathbb{Q}}
+        \def\R{\mathbb R}
+        \def\pair#1#2{(#1, #2)}
+        \let\eps\varepsilon
+        \let\oldZ=\Z
+        \renewcommand{\Z}{\oldZ_{0}}
+        \newcommand{\Rn}{\R^n}";
+        let test_list = vec![
+            (r"\N", "NN"),
+            (r"\Z", "ZZ_0^+"),
+            (r"\alpha \Q", "alpha QQ"),
+            (r"\R", "RR"),
+            (r"\pair{a}{b}", "(a, b)"),
+            (r"\eps", "epsilon"),
+            (r"\Rn", "RR^n"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_macros(tex, custom_macros).unwrap(), typst);
+        }
+
+        // arguments may follow after a space, as in \def\R{\mathbb R}
+        assert_eq!(tex2typst(r"\sqrt[3] x + \hat y").unwrap(), "root(3, x) + hat(y)");
+
+        // \newcommand does not replace a macro, and a macro cannot expand to itself forever
+        assert!(tex2typst_with_macros("x", r"\newcommand{\a}{1} \newcommand{\a}{2}").is_err());
+        assert!(tex2typst_with_macros(r"\a", r"\def\a{\a x}").is_err());
+
+        // a definition with delimited parameters is skipped without losing the others
+        let custom_macros = r"\def\a#1.{#1} \newcommand{\N}{\mathbb{N}}";
+        assert_eq!(tex2typst_with_macros(r"\N", custom_macros).unwrap(), "NN");
+
+        // an argument cannot be missing after the skipped spaces
+        assert!(tex2typst(r"\sqrt ").is_err());
+        assert!(tex2typst(r"\frac a ").is_err());
+        assert!(tex2typst(r"\mathbb ").is_err());
+    }
 }
 
 #[cfg(test)]
@@ -196,3 +272,948 @@ mod test_shorthand {
         assert_eq!(result, "--> ==> +- int_a^b");
     }
 }
+
+#[cfg(test)]
+mod test_limits {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_limits_and_nolimits() {
+        let test_list = vec![
+            (r"\sum\limits_{i}", "limits(sum)_i"),
+            (r"\int\nolimits_a^b f", "scripts(integral)_a^b f"),
+            (r"\sum \limits \nolimits_{i=1}^n i", "scripts(sum)_(i = 1)^n i"),
+            (r"\operatorname*{argmax}_{x} f", "op(\"argmax\", limits: #true)_x f"),
+            (r"\operatorname{argmax}\limits_{x}", "op(\"argmax\", limits: #true)_x"),
+            (r"\sum\limits i", "sum i"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\limits_a").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_math_class {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_math_class() {
+        let test_list = vec![
+            (r"a \mathrel{\sim} b", "a class(\"relation\", tilde.op) b"),
+            (r"a \mathbin{\#} b", "a class(\"binary\", \\#) b"),
+            (
+                r"\mathop{\mathrm{Res}}_{z=0} f",
+                "op(upright(R e s), limits: #true)_(z = 0) f",
+            ),
+            (
+                r"\mathopen{\langle} a \mathclose{\rangle}",
+                "class(\"opening\", angle.l) a class(\"closing\", angle.r)",
+            ),
+            (r"x \mathpunct{:} y", "x class(\"punctuation\", :) y"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_declarations {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_style_declarations() {
+        let test_list = vec![
+            (r"{\displaystyle \sum_{i=1}^n i} + 1", "display(sum_(i = 1)^n i) + 1"),
+            (r"a + {\textstyle \frac{1}{2}}", "a + inline(1/2)"),
+            (r"\frac{\scriptstyle a}{\scriptscriptstyle b}", "script(a)/sscript(b)"),
+            (
+                r"\begin{aligned} \displaystyle a &= b \\ c &= \textstyle d \end{aligned}",
+                "display(a) &= b \\ c &= inline(d)",
+            ),
+            (r"\displaystyle", ""),
+            (r"a + {\textstyle}", "a +"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_font_switches() {
+        let test_list = vec![
+            (r"\int f(x) {\rm d}x", "integral f(x) upright(d) x"),
+            (r"{\rm d x}", "upright(d x)"),
+            (
+                r"{\bf v} + {\it w} + {\sf a} + {\tt b} + {\cal C}",
+                "upright(bold(v)) + italic(w) + sans(a) + mono(b) + cal(C)",
+            ),
+            (r"\mathversion{bold} a + b", "bold(a + b)"),
+            (r"{\mathversion{normal} a}", "a"),
+            (r"x {\rm}", "x"),
+            (r"\mathversion{bold}", ""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_color {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_color_commands() {
+        let test_list = vec![
+            (r"\textcolor{red}{x} + y", "text(x, fill: red) + y"),
+            (r"a {\color{blue} b + c}", "a text(b + c, fill: blue)"),
+            (r"\colorbox{yellow}{x}", "box(x, fill: yellow, inset: #3pt)"),
+            (r"\textcolor{cyan}{x}", "text(x, fill: #rgb(\"#00ffff\"))"),
+            (r"\textcolor[rgb]{1,0.5,0}{x}", "text(x, fill: #rgb(100%, 50%, 0%))"),
+            (r"\textcolor[RGB]{255,128,0}{x}", "text(x, fill: #rgb(255, 128, 0))"),
+            (r"\textcolor[HTML]{FF8000}{x}", "text(x, fill: #rgb(\"#FF8000\"))"),
+            (r"\textcolor[gray]{0.25}{x}", "text(x, fill: #luma(25%))"),
+            (
+                r"\textcolor{red!50!blue}{x}",
+                "text(x, fill: #color.mix((red, 50%), (blue, 50%)))",
+            ),
+            (
+                r"\textcolor{red!30}{x}",
+                "text(x, fill: #color.mix((red, 30%), (white, 70%)))",
+            ),
+            (r"a \textcolor{red}{} b", "a b"),
+            (r"a {b \color{red}}", "a b"),
+            (r"\colorbox{red}{}", "box(fill: red, inset: #3pt)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\textcolor[rgb]{1,0}{x}").is_err());
+        assert!(tex2typst(r"\textcolor{ForestGreen}{x}").is_err());
+        assert!(tex2typst(r"\textcolor{red!50!MyColor}{x}").is_err());
+        assert!(tex2typst(r"\textcolor[HTML]{}{x}").is_err());
+        assert!(tex2typst(r"\textcolor[HTML]{FF80}{x}").is_err());
+        assert!(tex2typst(r"\textcolor[HTML]{GG8000}{x}").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_boxes_and_cancel {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_boxes() {
+        let test_list = vec![
+            (r"\boxed{E = m c^2}", "box(E = m c^2, inset: #3pt, stroke: #0.4pt)"),
+            (r"\fbox{see note}", "box(\"see note\", inset: #3pt, stroke: #0.4pt)"),
+            (r"\boxed{}", "box(inset: #3pt, stroke: #0.4pt)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_cancel() {
+        let test_list = vec![
+            (r"\cancel{x}", "cancel(x)"),
+            (r"\bcancel{x + 1}", "cancel(x + 1, inverted: #true)"),
+            (r"\xcancel{y}", "cancel(y, cross: #true)"),
+            (r"\cancelto{0}{x} + 1", "cancel(x)^0 + 1"),
+            (r"\frac{\cancel{a} b}{\cancel{a}}", "(cancel(a) b)/cancel(a)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_phantoms {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_phantoms_and_overlaps() {
+        let test_list = vec![
+            (r"a \phantom{+ b}", "a hide(+ b)"),
+            (r"\hphantom{x^2}", "box(hide(x^2), height: #0pt)"),
+            (r"\vphantom{\frac{1}{2}}", "box(hide(1/2), width: #0pt)"),
+            (
+                r"\smash{y} + \smash[b]{y}",
+                "box(y, height: #0pt) + box(y, height: #0pt)",
+            ),
+            (
+                r"\mathrlap{x} \mathllap{=} \mathclap{\sum}",
+                "box(x, width: #0pt) box(align(right, =), width: #0pt) box(align(center, sum), width: #0pt)",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_spacing {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_control_spaces() {
+        let test_list = vec![
+            (r"a\,b\:c\>d\;e", "a thin b med c med d thick e"),
+            (r"a\!b", "a #h(-0.1667em) b"),
+            (r"a\ b", "a space b"),
+            (r"f \quad (x)", "f quad (x)"),
+            (r"a \enspace b \negthinspace c", "a space.en b #h(-0.1667em) c"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_length_commands() {
+        let test_list = vec![
+            (r"a \hspace{1em} b", "a quad b"),
+            (r"a \hspace*{2pt} b", "a #h(2pt) b"),
+            (r"a \mkern-3mu b", "a #h(-0.1667em) b"),
+            (r"a \mkern3mu b \mspace{5mu} c", "a thin b thick c"),
+            (r"a \kern2pt b \kern-.5em c", "a #h(2pt) b #h(-0.5em) c"),
+            (r"a \hskip 1cm b", "a #h(1cm) b"),
+            (r"a \mkern 9mu b", "a #h(0.5em) b"),
+            (r"a \hspace{\fill} b", "a #h(1fr) b"),
+            (r"a \hskip\fill b \hspace{\stretch{2}} c", "a #h(1fr) b #h(2fr) c"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\kern").is_err());
+        assert!(tex2typst(r"a \kern2 b").is_err());
+        assert!(tex2typst(r"a \mkern-mu b").is_err());
+        assert!(tex2typst(r"a \hspace{em} b").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_negation {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_not() {
+        let test_list = vec![
+            (r"a \not= b", "a eq.not b"),
+            (r"x \not\in A", "x in.not A"),
+            (r"A \not \subset B", "A subset.not B"),
+            (r"a \not\equiv b \not< c", "a equiv.not b lt.not c"),
+            (r"a \not\sim b", "a tilde.not b"),
+            (r"a \not\leq b", "a lt.eq.not b"),
+            (r"a \not\perp b", "a class(\"relation\", perp \u{338}) b"),
+            (r"a \not: b", "a :\u{338} b"),
+            (r"a \nleqslant b \npreceq c", "a lt.eq.not b prec.curly.eq.not c"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_infix_fractions {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_infix_fractions() {
+        let test_list = vec![
+            (r"{n+1 \over 2}", "(n + 1)/2"),
+            (r"a \over b", "a/b"),
+            (r"x = {1 \over 2} + y", "x = 1/2 + y"),
+            (r"{n \choose k}", "binom(n, k)"),
+            (r"{a \atop b}", "mat(delim: #none, a; b)"),
+            (r"{n \brack k}", "mat(delim: \"[\", n; k)"),
+            (r"{n \brace k}", "mat(delim: \"{\", n; k)"),
+            (r"{{a \over b} \over c}", "a/b/c"),
+            (r"\sqrt{x \over 2}", "sqrt(x/2)"),
+            (r"{\displaystyle a \over b}", "display(a)/b"),
+            (
+                r"\begin{matrix} a \over b & c \\ d & e \choose f \end{matrix}",
+                "mat(delim: #none, a/b, c; d, binom(e, f))",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"{a \over b \over c}").is_err());
+        assert!(tex2typst(r"a \over").is_err());
+        assert!(tex2typst(r"\over b").is_err());
+        assert!(tex2typst(r"\begin{matrix} a & \over b \end{matrix}").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_generalized_fractions {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_cfrac() {
+        let test_list = vec![
+            (r"\cfrac{1}{2}", "display(1)/display(2)"),
+            (r"\cfrac[c]{1}{1 + x}", "display(1)/display(1 + x)"),
+            (
+                r"\cfrac{1}{1+\cfrac{1}{1+x}}",
+                "display(1)/display(1 + display(1)/display(1 + x))",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\cfrac[x]{1}{2}").is_err());
+        assert!(tex2typst(r"\cfrac[l]{1}{2}").is_err());
+        assert!(tex2typst(r"\cfrac[r]{1}{2}").is_err());
+    }
+
+    #[test]
+    fn test_genfrac() {
+        let test_list = vec![
+            (r"\genfrac{(}{)}{0pt}{}{n}{k}", "binom(n, k)"),
+            (r"\genfrac{}{}{}{}{a}{b}", "a/b"),
+            (r"\genfrac{}{}{0pt}{}{a}{b}", "mat(delim: #none, a; b)"),
+            (r"\genfrac{[}{]}{}{}{a}{b}", "[a/b]"),
+            (r"\genfrac{(}{)}{0pt}{1}{n}{k}", "inline(binom(n, k))"),
+            (r"\genfrac{}{}{1pt}{0}{a}{b}", "display(a/b)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\genfrac{}{}{}{5}{a}{b}").is_err());
+    }
+}
+
+#[cfg(test)]
+mod test_modular_arithmetic {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_modulo() {
+        let test_list = vec![
+            (r"a \equiv b \pmod{n}", "a equiv b space (mod n)"),
+            (r"a \equiv b \pmod n", "a equiv b space (mod n)"),
+            (r"a \equiv b \pod{n}", "a equiv b space (n)"),
+            (r"a \equiv b \mod{n}", "a equiv b quad mod space n"),
+            (r"a \equiv b \mod 7", "a equiv b quad mod space 7"),
+            (r"a \bmod b", "a class(\"binary\", mod) b"),
+            (r"x \equiv 1 \pmod{p^2}", "x equiv 1 space (mod p^2)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_dots {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_context_dots() {
+        let test_list = vec![
+            (r"a_1 + \dots + a_n", "a_1 + dots.c + a_n"),
+            (r"a_1, \dots, a_n", "a_1, dots.h, a_n"),
+            (r"x_1 \leq \dots \leq x_n", "x_1 lt.eq dots.c lt.eq x_n"),
+            (r"1 + 2 + \dots", "1 + 2 + dots.c"),
+            (r"(1, 2, \dots)", "(1, 2, dots.h)"),
+            (r"\dots", "dots.h"),
+            (r"a_1 \cdot \dots \cdot a_n", "a_1 dot.op dots.c dot.op a_n"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_explicit_dots() {
+        let test_list = vec![
+            (r"a_1, \dotsc, a_n", "a_1, dots.h, a_n"),
+            (r"a_1 + \dotsb + a_n", "a_1 + dots.c + a_n"),
+            (r"a_1 \dotsm a_n", "a_1 dots.c a_n"),
+            (r"\int \dotsi \int", "integral dots.c integral"),
+            (r"A \dotso B", "A dots.h B"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_abs_and_norm {
+    use crate::tex2typst;
+
+    #[test]
+    fn test_paired_bars() {
+        let test_list = vec![
+            (r"|x|", "abs(x)"),
+            (r"|x - y| + |y|", "abs(x - y) + abs(y)"),
+            (r"\|v\|", "norm(v)"),
+            (r"\lvert x \rvert", "abs(x)"),
+            (r"\lVert v \rVert_2", "norm(v)_2"),
+            (r"|x|^2", "abs(x)^2"),
+            (r"\|x - |y|\|", "norm(x - abs(y))"),
+            (r"\left| x \right|", "abs(x)"),
+            (r"\left\| v \right\|", "norm(v)"),
+            (r"\left\lvert \frac{1}{2} \right\rvert", "abs(1/2)"),
+            (r"\{x \mid |x| < 1\}", "{x divides abs(x) < 1}"),
+            (r"P(A|B)", "P(A|B)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_differentials {
+    use crate::command_registry::{parse_custom_macros, CommandRegistry};
+    use crate::converter::ConverterOptions;
+    use crate::{tex2typst, tex2typst_with_options, tex2typst_with_registry};
+
+    #[test]
+    fn test_detect_differentials() {
+        let options = ConverterOptions {
+            detect_differentials: true,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (r"\int f(x) dx", "integral f(x) dif x"),
+            (r"\int_0^1 x^2 \, dx", "integral_0^1 x^2 dif x"),
+            (r"\int \mathrm{d}x", "integral dif x"),
+            (r"\iint f \, {\rm d}x \, {\rm d}y", "integral.double f dif x dif y"),
+            (r"\int e^{2t} d\theta", "integral e^(2 t) dif theta"),
+            (r"\frac{d}{dx}", "dif/(dif x)"),
+            (r"\frac{dy}{dx}", "(dif y)/(dif x)"),
+            (r"\frac{d^2 y}{dx^2}", "(dif^2 y)/(dif x^2)"),
+            (r"\frac{\partial f}{\partial x}", "(diff f)/(diff x)"),
+            (r"a + d x", "a + d x"),
+            (r"\frac{a}{dx}", "a/(d x)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+        // the integrand ends after its differentials or at a sign
+        assert_eq!(
+            tex2typst_with_options(r"\int dx\, f(x) + g d y", &options).unwrap(),
+            "integral dif x thin f(x) + g d y"
+        );
+        assert_eq!(
+            tex2typst_with_options(r"\int f dx + g d y", &options).unwrap(),
+            "integral f dif x + g d y"
+        );
+        // the pass is opt-in
+        assert_eq!(tex2typst(r"\int f(x) dx").unwrap(), "integral f(x) d x");
+    }
+
+    #[test]
+    fn test_italic_differentials() {
+        let options = ConverterOptions {
+            detect_differentials: true,
+            italic_differential: true,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (r"\int f(x) dx", "integral f(x) thin d x"),
+            (r"\int f(x) \, dx", "integral f(x) thin d x"),
+            (r"\frac{dy}{dx}", "(d y)/(d x)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_differentials_with_macros() {
+        let options = ConverterOptions {
+            detect_differentials: true,
+            ..Default::default()
+        };
+        let mut registry = CommandRegistry::new();
+        registry.register_custom_macros(parse_custom_macros(r"\newcommand{\dx}{\,dx}").unwrap());
+        let result = tex2typst_with_registry(r"\int f(x) \dx", &registry, &options).unwrap();
+        assert_eq!(result, "integral f(x) dif x");
+    }
+}
+
+#[cfg(test)]
+mod test_multi_letter_text {
+    use crate::converter::ConverterOptions;
+    use crate::{tex2typst, tex2typst_with_options};
+
+    #[test]
+    fn test_multi_letter_text() {
+        let options = ConverterOptions {
+            multi_letter_text: true,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (r"x_{max}", "x_\"max\""),
+            (r"\mathrm{abc}", "upright(\"abc\")"),
+            (r"\mathrm{d}", "upright(d)"),
+            (r"T_{i,max}", "T_(i, \"max\")"),
+            (r"a_{ij} + b_{xy}", "a_(i j) + b_(x y)"),
+            (r"x_{n+1}", "x_(n + 1)"),
+            (r"\mathsf{Var}(X)", "sans(\"Var\")(X)"),
+            (r"xyz \cdot ab", "x y z dot.op a b"),
+            (r"A_{xyz} + B_{xy}", "A_(x y z) + B_(x y)"),
+            (r"R_{ijkl} = g_{nm} a_{ijk}", "R_(i j k l) = g_(n m) a_(i j k)"),
+            (r"T_{min} + P_{in} + E_{kin}", "T_\"min\" + P_\"in\" + E_\"kin\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+        // off by default
+        assert_eq!(tex2typst(r"x_{max}").unwrap(), "x_(m a x)");
+    }
+
+    #[test]
+    fn test_variable_products() {
+        let options = ConverterOptions {
+            multi_letter_text: true,
+            variable_products: vec!["abc".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            tex2typst_with_options(r"x_{abc} + x_{abd}", &options).unwrap(),
+            "x_(a b c) + x_\"abd\""
+        );
+    }
+}
+
+#[cfg(test)]
+mod test_alphabets {
+    use crate::converter::{ConverterOptions, StylePreset};
+    use crate::{tex2typst, tex2typst_with_options};
+
+    #[test]
+    fn test_tex_alphabets() {
+        let test_list = vec![
+            (r"\mathbb{R}", "RR"),
+            (r"\mathbb{1}", "bb(1)"),
+            (r"\mathbb{k}", "bb(k)"),
+            (r"\mathbf{x}", "upright(bold(x))"),
+            (r"\mathbf{x+y}", "upright(bold(x + y))"),
+            (r"\mathbf{\alpha}", "alpha"),
+            (r"\mathbf{\Gamma}", "upright(bold(Gamma))"),
+            (r"\mathbf{2}", "upright(bold(2))"),
+            (r"\boldsymbol{\alpha}", "bold(alpha)"),
+            (r"\bm{x}", "bold(x)"),
+            (r"\mathbfit{A}", "bold(italic(A))"),
+            (r"\mathsfit{x}", "sans(italic(x))"),
+            (r"\mathcal{L}", "cal(L)"),
+            (r"\mathfrak{g}", "frak(g)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_iso_alphabets() {
+        let options = ConverterOptions {
+            style_preset: StylePreset::Iso,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (r"\mathbf{x}", "bold(x)"),
+            (r"\mathbf{\alpha}", "bold(alpha)"),
+            (r"\mathbf{\Gamma}", "bold(italic(Gamma))"),
+            (r"\mathbf{A} \mathbf{x} = \mathbf{b}", "bold(A) bold(x) = bold(b)"),
+            (r"\mathbb{R}", "RR"),
+            (r"\boldsymbol{\alpha}", "bold(alpha)"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_physics {
+    use crate::command_registry::CommandRegistry;
+    use crate::converter::ConverterOptions;
+    use crate::tex2typst_with_registry;
+
+    fn physics2typst(tex: &str) -> Result<String, String> {
+        let mut registry = CommandRegistry::new();
+        registry.register_physics_macros();
+        tex2typst_with_registry(tex, &registry, &ConverterOptions::default())
+    }
+
+    #[test]
+    fn test_physics_derivatives() {
+        let test_list = vec![
+            (r"\dv{f}{x}", "(dif f)/(dif x)"),
+            (r"\dv{x}", "dif/(dif x)"),
+            (r"\dv[2]{f}{x}", "(dif^2 f)/(dif x^2)"),
+            (r"\pdv{f}{x}", "(diff f)/(diff x)"),
+            (r"\pdv[2]{f}{x}", "(diff^2 f)/(diff x^2)"),
+            (r"\pdv{f}{x}{y}", "(diff^2 f)/(diff x diff y)"),
+            (r"\dv*{f}{x}", "dif f slash dif x"),
+            (r"\pdv*[2]{f}{x}", "diff^2 f slash diff x^2"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(physics2typst(tex).unwrap(), typst);
+        }
+        // an order cannot be given to a mixed derivative
+        assert!(physics2typst(r"\pdv[2]{f}{x}{y}").is_err());
+    }
+
+    #[test]
+    fn test_physics_delimiters() {
+        let test_list = vec![
+            (r"\abs{x}", "abs(x)"),
+            (r"\norm{v}", "norm(v)"),
+            (r"\abs{\norm{v}}", "abs(norm(v))"),
+            (r"\bra{\psi}", "lr(angle.l psi|)"),
+            (r"\ket{\psi}", "lr(|psi angle.r)"),
+            (r"\braket{a}{b}", "lr(angle.l a mid(|) b angle.r)"),
+            (r"\expval{A}", "lr(angle.l A angle.r)"),
+            (r"\expval{A}{\psi}", "lr(angle.l psi mid(|) A mid(|) psi angle.r)"),
+            (r"\mel{a}{H}{b}", "lr(angle.l a mid(|) H mid(|) b angle.r)"),
+            (r"\qty(\frac{a}{b})", "(a/b)"),
+            (r"\qty[x]", "[x]"),
+            (r"\abs*{x}", "abs(x)"),
+            (r"\norm*{v}", "norm(v)"),
+            (r"\braket*{a}{b}", "lr(angle.l a mid(|) b angle.r)"),
+            (r"\qty\Big(x)", "(x)"),
+            (r"\qty*[x]", "[x]"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(physics2typst(tex).unwrap(), typst);
+        }
+        assert!(physics2typst(r"\mel{a}{b}").is_err());
+        assert_eq!(
+            physics2typst(r"\bra{\phi}\ket{\psi}").unwrap(),
+            "lr(angle.l phi.alt mid(|) psi angle.r)"
+        );
+    }
+
+    #[test]
+    fn test_physics_operators() {
+        let test_list = vec![
+            (r"\grad f", "gradient f"),
+            (r"\curl \vb{A}", "gradient times upright(bold(A))"),
+            (r"\vu{x}", "hat(upright(bold(x)))"),
+            (r"\tr A", "tr A"),
+            (r"\Tr \rho", "op(\"Tr\") rho"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(physics2typst(tex).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_dirac {
+    use crate::command_registry::CommandRegistry;
+    use crate::converter::ConverterOptions;
+    use crate::{tex2typst, tex2typst_with_registry};
+
+    #[test]
+    fn test_hand_written_dirac() {
+        let test_list = vec![
+            (r"\langle\psi|", "lr(angle.l psi|)"),
+            (r"|\psi\rangle", "lr(|psi angle.r)"),
+            (
+                r"\langle \phi | A | \psi \rangle",
+                "lr(angle.l phi.alt mid(|) A mid(|) psi angle.r)",
+            ),
+            (r"\langle a | b \rangle", "lr(angle.l a mid(|) b angle.r)"),
+            (r"|a\rangle\langle b|", "lr(|a angle.r) lr(angle.l b|)"),
+            (r"\left\langle a | b \right\rangle", "lr(angle.l a mid(|) b angle.r)"),
+            (r"\langle x, y \rangle", "angle.l x, y angle.r"),
+            (r"|x| + |y|", "abs(x) + abs(y)"),
+            (r"\langle |x| \rangle", "lr(angle.l abs(x) angle.r)"),
+            (r"\langle |\psi|^2 \rangle", "lr(angle.l abs(psi)^2 angle.r)"),
+            (
+                r"\langle \phi | A |x| \rangle",
+                "lr(angle.l phi.alt mid(|) A abs(x) angle.r)",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_braket_package() {
+        let test_list = vec![
+            (r"\Bra{\psi}", "lr(angle.l psi|)"),
+            (r"\Ket{\psi}", "lr(|psi angle.r)"),
+            (
+                r"\Braket{ \phi | A | \psi }",
+                "lr(angle.l phi.alt mid(|) A mid(|) psi angle.r)",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        // the lowercase commands are those of the physics macro pack
+        let mut registry = CommandRegistry::new();
+        registry.register_physics_macros();
+        let result = tex2typst_with_registry(r"\braket{a}", &registry, &ConverterOptions::default()).unwrap();
+        assert_eq!(result, "lr(angle.l a mid(|) a angle.r)");
+    }
+}
+
+#[cfg(test)]
+mod test_siunitx {
+    use crate::command_registry::CommandRegistry;
+    use crate::converter::ConverterOptions;
+    use crate::siunitx::SiunitxTarget;
+    use crate::{tex2typst, tex2typst_with_options, tex2typst_with_registry};
+
+    #[test]
+    fn test_units() {
+        let test_list = vec![
+            (
+                r"\SI{9.81}{\meter\per\second\squared}",
+                "9.81 thin \"m\" dot \"s\"^(-2)",
+            ),
+            (r"\qty{3}{\kilo\gram}", "3 thin \"kg\""),
+            (r"\si{\newton\meter}", "\"N\" dot \"m\""),
+            (r"\unit{\square\centi\metre}", "\"cm\"^2"),
+            (r"\si{kg.m/s^2}", "\"kg\" dot \"m\" dot \"s\"^(-2)"),
+            (r"\SI[per-mode=symbol]{5}{\micro\ampere}", "5 thin \"µA\""),
+            (r"\SI{30}{\degree}", "30 \"°\""),
+            (
+                r"v = \SI{3e8}{\meter\per\second}",
+                "v = 3 times 10^8 thin \"m\" dot \"s\"^(-1)",
+            ),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\si{\furlong}").is_err());
+        for tex in [
+            r"\SI{1}{}",
+            r"\SI{}{\meter}",
+            r"\si{}",
+            r"\num{}",
+            r"\num{-}",
+            r"\ang{}",
+            r"\ang{;;}",
+        ] {
+            assert!(tex2typst(tex).is_err(), "{}", tex);
+        }
+    }
+
+    #[test]
+    fn test_abbreviated_units() {
+        let test_list = vec![
+            (r"\SI{3}{\km}", "3 thin \"km\""),
+            (r"\qty{2.4}{\GHz}", "2.4 thin \"GHz\""),
+            (r"\SI{5}{\kg\per\cubic\m}", "5 thin \"kg\" dot \"m\"^(-3)"),
+            (r"\si{\ms}", "\"ms\""),
+            (r"\SI{10}{\uF}", "10 thin \"µF\""),
+            (r"\SI{1}{\kohm}", "1 thin \"kΩ\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_qty_with_physics_pack() {
+        // with the physics pack, \qty with two braced arguments stays a siunitx quantity
+        let mut registry = CommandRegistry::new();
+        registry.register_physics_macros();
+        let options = ConverterOptions::default();
+        let test_list = vec![
+            (r"\qty(x)", "(x)"),
+            (r"\qty{x}", "{x}"),
+            (r"\qty(x){y}", "(x) y"),
+            (r"\qty{3}{\meter}", "3 thin \"m\""),
+            (r"\qty[per-mode=symbol]{3}{\km}", "3 thin \"km\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(
+                tex2typst_with_registry(tex, &registry, &options).unwrap(),
+                typst,
+                "{}",
+                tex
+            );
+        }
+        assert_eq!(tex2typst(r"\qty{3}{\km}").unwrap(), "3 thin \"km\"");
+    }
+
+    #[test]
+    fn test_numbers() {
+        let test_list = vec![
+            (r"\num{1.2e-3}", "1.2 times 10^(-3)"),
+            (r"\num{12345.67891}", "12 thin 345.678 thin 91"),
+            (r"\num{12345.6789}", "12 thin 345.6789"),
+            (r"\num{1234}", "1234"),
+            (r"\num{1,5}", "1.5"),
+            (r"\num{-4.5e10}", "-4.5 times 10^10"),
+            (r"\num{e5}", "10^5"),
+            (r"\num{1.2 +- 0.1}", "1.2 plus.minus 0.1"),
+            (r"\num{2x3}", "2 times 3"),
+            (r"\ang{30}", "30 \"°\""),
+            (r"\ang{1;2;3}", "1 \"°\" 2 \"′\" 3 \"″\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_unify_target() {
+        let options = ConverterOptions {
+            siunitx_target: SiunitxTarget::Unify,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (
+                r"\SI{9.81}{\meter\per\second\squared}",
+                "#qty(\"9.81\", \"meter per second squared\")",
+            ),
+            (r"\num{1.2e-3}", "#num(\"1.2e-3\")"),
+            (r"\si{\kilo\gram}", "#unit(\"kilo gram\")"),
+            (r"\ang{30}", "#qty(\"30\", \"degree\")"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_mhchem {
+    use crate::converter::ConverterOptions;
+    use crate::mhchem::MhchemTarget;
+    use crate::{tex2typst, tex2typst_with_options};
+
+    #[test]
+    fn test_formulas() {
+        let test_list = vec![
+            (r"\ce{H2O}", "\"H\"_2\"O\""),
+            (r"\ce{SO4^2-}", "\"SO\"_4^(2-)"),
+            (r"\ce{OH-}", "\"OH\"^(-)"),
+            (r"\ce{Ca(OH)2}", "\"Ca\"(\"OH\")_2"),
+            (r"\ce{[Cu(NH3)4]^2+}", "[\"Cu\"(\"NH\"_3)_4]^(2+)"),
+            (r"\ce{CuSO4.5H2O}", "\"CuSO\"_4 dot 5 \"H\"_2\"O\""),
+            (r"\ce{NaCl(aq)}", "\"NaCl\"\"(aq)\""),
+            (r"\ce{Fe^{III}}", "\"Fe\"^\"III\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_reactions() {
+        let test_list = vec![
+            (
+                r"\ce{H2SO4 -> 2H+ + SO4^2-}",
+                "\"H\"_2\"SO\"_4 arrow.r 2 \"H\"^(+) + \"SO\"_4^(2-)",
+            ),
+            (
+                r"\ce{A <=>[catalyst] B}",
+                "\"A\" stretch(harpoons.rtlb)^\"catalyst\" \"B\"",
+            ),
+            (
+                r"\ce{A ->[{heat}][H2O] B}",
+                "\"A\" stretch(arrow.r)^\"heat\"_(\"H\"_2\"O\") \"B\"",
+            ),
+            (
+                r"\ce{CaCO3(s) -> CaO(s) + CO2 ^}",
+                "\"CaCO\"_3\"(s)\" arrow.r \"CaO\"\"(s)\" + \"CO\"_2 arrow.t",
+            ),
+            (
+                r"\ce{Ag+ + Cl- -> AgCl v}",
+                "\"Ag\"^(+) + \"Cl\"^(-) arrow.r \"AgCl\" arrow.b",
+            ),
+            (r"\ce{A->B}", "\"A\" arrow.r \"B\""),
+            (r"\ce{A<=>B}", "\"A\" harpoons.rtlb \"B\""),
+            (r"\ce{A ->[\Delta] B}", "\"A\" stretch(arrow.r)^Delta \"B\""),
+            (r"\ce{A->[$\Delta H$]B}", "\"A\" stretch(arrow.r)^(Delta H) \"B\""),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+        assert!(tex2typst(r"\ce{A ->[cat B}").is_err());
+        assert!(tex2typst(r"\ce{}").is_err());
+        assert!(tex2typst(r"\pu{ }").is_err());
+    }
+
+    #[test]
+    fn test_physical_units() {
+        let test_list = vec![
+            (r"\pu{123 kJ/mol}", "123 thin \"kJ\" dot \"mol\"^(-1)"),
+            (
+                r"\pu{8.314 J K-1 mol-1}",
+                "8.314 thin \"J\" dot \"K\"^(-1) dot \"mol\"^(-1)",
+            ),
+            (r"\pu{1.2e3 m2}", "1.2 times 10^3 thin \"m\"^2"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_typsium_target() {
+        let options = ConverterOptions {
+            mhchem_target: MhchemTarget::Typsium,
+            ..Default::default()
+        };
+        let test_list = vec![
+            (r"\ce{H2SO4 -> 2H+ + SO4^2-}", "#ce(\"H2SO4 -> 2H+ + SO4^2-\")"),
+            (r"\ce{A <=>[catalyst] B}", "#ce(\"A <=>[catalyst] B\")"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_options(tex, &options).unwrap(), typst);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test_mathtools {
+    use crate::{tex2typst, tex2typst_with_macros};
+
+    #[test]
+    fn test_colon_equals() {
+        let test_list = vec![
+            (r"x \coloneqq 1", "x colon.eq 1"),
+            (r"1 \eqqcolon x", "1 eq.colon x"),
+            (r"x \Coloneqq y", "x colon.double.eq y"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst(tex).unwrap(), typst);
+        }
+    }
+
+    #[test]
+    fn test_paired_delimiters() {
+        let custom_macros = r"\DeclarePairedDelimiter{\abs}{\lvert}{\rvert}
+\DeclarePairedDelimiter\ip{\langle}{\rangle}
+\DeclarePairedDelimiterX{\set}[2]{\{}{\}}{#1 \delimsize| #2}
+\DeclarePairedDelimiterX{\vnorm}[1]{\lVert}{\rVert}{\mathbf#1}";
+        let test_list = vec![
+            (r"\abs{x}", "abs(x)"),
+            (r"\abs*{\frac{a}{b}}", "abs(a/b)"),
+            (r"\abs[\big]{x}", "abs(x)"),
+            (r"\ip{u, v}", "angle.l u, v angle.r"),
+            (r"\ip*{u, v}", "lr(angle.l u comma v angle.r)"),
+            (r"\set{x}{x > 0}", "{x|x > 0}"),
+            (r"\set*{x}{x > 0}", "{x mid(|) x > 0}"),
+            (r"\abs{\alpha x}", "abs(alpha x)"),
+            (r"\vnorm{v}", "norm(upright(bold(v)))"),
+        ];
+        for (tex, typst) in test_list {
+            assert_eq!(tex2typst_with_macros(tex, custom_macros).unwrap(), typst);
+        }
+        assert!(tex2typst_with_macros(r"\set{x}", custom_macros).is_err());
+    }
+}
@@ -24,10 +24,19 @@ impl LatexParser {
     }
 
     pub fn parse(&self, tokens: Vec<TexToken>) -> Result<TexNode, String> {
+        let infix_positions = find_infix_commands(&tokens);
+        if !infix_positions.is_empty() {
+            return self.parse_infix_fraction(tokens, &infix_positions);
+        }
         let mut results: Vec<TexNode> = Vec::new();
         let mut pos = 0;
 
         while pos < tokens.len() {
+            if self.is_declaration(&tokens[pos]) {
+                // the declaration takes over the rest of the group
+                results.push(self.parse_declaration(&tokens, pos)?);
+                break;
+            }
             let (res, new_pos) = self.parse_next_expr(&tokens, pos)?;
             pos = new_pos;
             if res.node_type == TexNodeType::Whitespace
@@ -52,8 +61,55 @@ impl LatexParser {
         }
     }
 
+    // {a \over b} -> \frac{a}{b}, {n \choose k} -> \binom{n}{k}
+    // \atop, \brack and \brace have no amsmath counterpart and are kept as binary nodes
+    fn parse_infix_fraction(&self, mut tokens: Vec<TexToken>, infix_positions: &[usize]) -> Result<TexNode, String> {
+        if infix_positions.len() > 1 {
+            return Err("Ambiguous infix fraction, more than one in the same group".to_string());
+        }
+        let pos = infix_positions[0];
+        let denominator = tokens.split_off(pos + 1);
+        let command = tokens.pop().unwrap().value;
+        let is_blank = |tokens: &[TexToken]| {
+            tokens
+                .iter()
+                .all(|token| matches!(token.token_type, TexTokenType::Space | TexTokenType::Newline))
+        };
+        if is_blank(&tokens) || is_blank(&denominator) {
+            return Err(format!("Missing operand for {}", command));
+        }
+        let command = match command.as_str() {
+            "\\over" => "\\frac".to_string(),
+            "\\choose" => "\\binom".to_string(),
+            _ => command,
+        };
+        let numerator = self.parse(tokens)?;
+        let denominator = self.parse(denominator)?;
+        Ok(TexNode::new(
+            TexNodeType::BinaryFunc,
+            command,
+            Some(vec![numerator, denominator]),
+            None,
+        ))
+    }
+
     fn parse_next_expr(&self, tokens: &[TexToken], start: usize) -> ParseResult {
-        let (base, mut pos) = self.parse_next_expr_without_supsub(tokens, start)?;
+        let (mut base, mut pos) = self.parse_next_expr_without_supsub(tokens, start)?;
+        // \limits and \nolimits modify the operator right before them, the last one wins
+        loop {
+            let next = pos + eat_whitespaces(tokens, pos);
+            if next < tokens.len() && (tokens[next].eq(&LIMITS_COMMAND) || tokens[next].eq(&NOLIMITS_COMMAND)) {
+                base = TexNode::new(
+                    TexNodeType::UnaryFunc,
+                    tokens[next].value.clone(),
+                    Some(vec![strip_limits_modifier(base)]),
+                    None,
+                );
+                pos = next + 1;
+            } else {
+                break;
+            }
+        }
         let mut sub: Option<TexNode> = None;
         let mut sup: Option<TexNode> = None;
         let mut num_prime = 0;
@@ -189,8 +245,8 @@ impl LatexParser {
                                 TexNode::new(TexNodeType::Control, "\\\\".to_string(), None, None),
                                 start + 1,
                             )),
-                            "\\," => Ok((
-                                TexNode::new(TexNodeType::Control, "\\,".to_string(), None, None),
+                            "\\," | "\\:" | "\\>" | "\\;" | "\\!" | "\\ " => Ok((
+                                TexNode::new(TexNodeType::Control, control_char.clone(), None, None),
                                 start + 1,
                             )),
                             "_" | "^" => Ok((EMPTY_NODE.clone(), start)),
@@ -217,10 +273,25 @@ impl LatexParser {
         if matches!(command[1..].as_ref(), "left" | "right" | "begin" | "end") {
             return Err(format!("Unexpected command: {}", command));
         }
+        if matches!(command[1..].as_ref(), "limits" | "nolimits") {
+            return Err(format!("{} must follow an operator", command));
+        }
+        if command == "\\middle" {
+            // \middle| inside \left \right, the delimiter is kept as an element
+            if pos >= tokens.len() || eat_parenthesis(tokens, pos).is_none() {
+                return Err("Invalid delimiter after \\middle".to_string());
+            }
+            let delimiter = TexNode::new(TexNodeType::Element, tokens[pos].value.clone(), None, None);
+            return Ok((
+                TexNode::new(TexNodeType::UnaryFunc, command.clone(), Some(vec![delimiter]), None),
+                pos + 1,
+            ));
+        }
 
         match self.command_registry.get_command_type(&command[1..]) {
             Some(CommandType::Symbol) => {
-                if !SYMBOL_MAP.contains_key(&command[1..]) {
+                // \bmod is built by the converter as it is not a plain Typst symbol
+                if !SYMBOL_MAP.contains_key(&command[1..]) && command != "\\bmod" {
                     return Ok((
                         TexNode::new(TexNodeType::UnknownMacro, command.clone(), None, None),
                         pos,
@@ -242,14 +313,58 @@ impl LatexParser {
                     let text = tokens[pos + 1].value.clone();
                     return Ok((TexNode::new(TexNodeType::Text, text, None, None), pos + 3));
                 }
+                // as in TeX, spaces before an argument are skipped, e.g. \mathbb R, \not = or \mod 3
+                let pos = pos + eat_whitespaces(tokens, pos);
+                if pos >= tokens.len() {
+                    return Err("Unexpected end of input".to_string());
+                }
                 let (arg1, new_pos) = self.parse_next_expr_without_supsub(tokens, pos)?;
+                if let Some((left, right)) = braket_delimiters(command) {
+                    // \Bra{x} -> \left\langle x \right|, the bars inside \Braket{a | b} become \middle in the converter
+                    let delimiter = |value: &str| TexNode::new(TexNodeType::Element, value.to_string(), None, None);
+                    return Ok((
+                        TexNode::new(
+                            TexNodeType::Leftright,
+                            String::new(),
+                            Some(vec![delimiter(left), arg1, delimiter(right)]),
+                            None,
+                        ),
+                        new_pos,
+                    ));
+                }
+                if command == "\\operatorname*" {
+                    // \operatorname*{foo} is the same as \operatorname{foo}\limits
+                    let operator = TexNode::new(
+                        TexNodeType::UnaryFunc,
+                        "\\operatorname".to_string(),
+                        Some(vec![arg1]),
+                        None,
+                    );
+                    return Ok((
+                        TexNode::new(
+                            TexNodeType::UnaryFunc,
+                            "\\limits".to_string(),
+                            Some(vec![operator]),
+                            None,
+                        ),
+                        new_pos,
+                    ));
+                }
                 Ok((
                     TexNode::new(TexNodeType::UnaryFunc, command.clone(), Some(vec![arg1]), None),
                     new_pos,
                 ))
             }
             Some(CommandType::Binary) => {
+                let pos = pos + eat_whitespaces(tokens, pos);
+                if pos >= tokens.len() {
+                    return Err("Unexpected end of input".to_string());
+                }
                 let (arg1, pos1) = self.parse_next_expr_without_supsub(tokens, pos)?;
+                let pos1 = pos1 + eat_whitespaces(tokens, pos1);
+                if pos1 >= tokens.len() {
+                    return Err("Unexpected end of input".to_string());
+                }
                 let (arg2, pos2) = self.parse_next_expr_without_supsub(tokens, pos1)?;
                 Ok((
                     TexNode::new(TexNodeType::BinaryFunc, command.clone(), Some(vec![arg1, arg2]), None),
@@ -257,6 +372,10 @@ impl LatexParser {
                 ))
             }
             Some(CommandType::OptionalBinary) => {
+                let pos = pos + eat_whitespaces(tokens, pos);
+                if pos >= tokens.len() {
+                    return Err("Unexpected end of input".to_string());
+                }
                 let mut args = vec![];
                 let mut new_pos = pos;
                 if tokens[pos].token_type == TexTokenType::Element && tokens[pos].value == "[" {
@@ -268,8 +387,13 @@ impl LatexParser {
                     }
                     let optional_arg_inside = &tokens[pos_left_square_bracket + 1..pos_right_square_bracket as usize];
                     let optional_arg_node = self.parse(optional_arg_inside.to_vec())?;
+                    let pos_mandatory_arg = pos_right_square_bracket as usize + 1;
+                    let pos_mandatory_arg = pos_mandatory_arg + eat_whitespaces(tokens, pos_mandatory_arg);
+                    if pos_mandatory_arg >= tokens.len() {
+                        return Err("Unexpected end of input".to_string());
+                    }
                     let (mandatory_arg_node, _new_pos) =
-                        self.parse_next_expr_without_supsub(tokens, pos_right_square_bracket as usize + 1)?;
+                        self.parse_next_expr_without_supsub(tokens, pos_mandatory_arg)?;
                     args.push(optional_arg_node);
                     args.push(mandatory_arg_node);
                     new_pos = _new_pos;
@@ -283,10 +407,229 @@ impl LatexParser {
                     new_pos,
                 ))
             }
+            Some(CommandType::Color) => {
+                let (color, pos1) = self.parse_color(tokens, pos)?;
+                let (body, pos2) = self.parse_next_expr_without_supsub(tokens, pos1)?;
+                Ok((
+                    TexNode::new(TexNodeType::BinaryFunc, command.clone(), Some(vec![color, body]), None),
+                    pos2,
+                ))
+            }
+            Some(CommandType::Length) => {
+                let (length, new_pos) = parse_length_arg(tokens, pos)?;
+                let length = TexNode::new(TexNodeType::Text, length, None, None);
+                Ok((
+                    TexNode::new(TexNodeType::UnaryFunc, command.clone(), Some(vec![length]), None),
+                    new_pos,
+                ))
+            }
+            Some(CommandType::GeneralizedFraction) => self.parse_generalized_fraction(tokens, start),
+            Some(CommandType::Siunitx) => self.parse_siunitx(tokens, start),
+            Some(CommandType::Mhchem) => {
+                let (text, new_pos) = parse_raw_text_arg(tokens, pos)?;
+                let text = TexNode::new(TexNodeType::Text, text, None, None);
+                Ok((
+                    TexNode::new(TexNodeType::UnaryFunc, command.clone(), Some(vec![text]), None),
+                    new_pos,
+                ))
+            }
+            Some(CommandType::Declaration) => Err(format!("Unexpected {} outside of a group", command)),
             _ => Err("Invalid number of parameters".to_string()),
         }
     }
 
+    // \cfrac[l]{a}{b} -> \frac{\displaystyle a}{\displaystyle b}
+    // \genfrac{(}{)}{0pt}{}{a}{b} -> \binom{a}{b}, other delimiters, rules and styles are expressed
+    // with \left \right, \atop and the style declarations
+    fn parse_generalized_fraction(&self, tokens: &[TexToken], start: usize) -> ParseResult {
+        let command = tokens[start].value.as_str();
+        let mut pos = start + 1;
+        if command == "\\cfrac" {
+            pos += eat_whitespaces(tokens, pos);
+            if pos < tokens.len() && tokens[pos].eq(&LEFT_SQUARE_BRACKET) {
+                let pos_right_square_bracket =
+                    find_closing_match(tokens, pos, &LEFT_SQUARE_BRACKET, &RIGHT_SQUARE_BRACKET);
+                if pos_right_square_bracket == -1 {
+                    return Err("No matching right square bracket for [".to_string());
+                }
+                let alignment = tokens[pos + 1..pos_right_square_bracket as usize]
+                    .iter()
+                    .map(|token| token.value.as_str())
+                    .collect::<String>();
+                // Typst always centers the numerator and has no way to shift it inside the fraction
+                match alignment.trim() {
+                    "c" => {}
+                    "l" | "r" => return Err(format!("Unsupported alignment for \\cfrac: {}", alignment)),
+                    _ => return Err(format!("Invalid alignment for \\cfrac: {}", alignment)),
+                }
+                pos = pos_right_square_bracket as usize + 1;
+            }
+            let (numerator, pos1) = self.parse_next_expr_without_supsub(tokens, pos)?;
+            let (denominator, pos2) = self.parse_next_expr_without_supsub(tokens, pos1)?;
+            let display = |node: TexNode| {
+                TexNode::new(
+                    TexNodeType::UnaryFunc,
+                    "\\displaystyle".to_string(),
+                    Some(vec![node]),
+                    None,
+                )
+            };
+            return Ok((
+                TexNode::new(
+                    TexNodeType::BinaryFunc,
+                    "\\frac".to_string(),
+                    Some(vec![display(numerator), display(denominator)]),
+                    None,
+                ),
+                pos2,
+            ));
+        }
+
+        let (left, pos) = parse_delimiter_arg(tokens, pos)?;
+        let (right, pos) = parse_delimiter_arg(tokens, pos)?;
+        let (thickness, pos) = parse_raw_text_arg(tokens, pos)?;
+        let (style, pos) = parse_raw_text_arg(tokens, pos)?;
+        let (numerator, pos) = self.parse_next_expr_without_supsub(tokens, pos)?;
+        let (denominator, pos) = self.parse_next_expr_without_supsub(tokens, pos)?;
+
+        let without_rule = !thickness.is_empty()
+            && thickness
+                .trim_end_matches(|c: char| c.is_ascii_alphabetic())
+                .parse::<f64>()
+                .is_ok_and(|value| value == 0.0);
+        let binomial = without_rule && left == "(" && right == ")";
+        let fraction_command = if binomial {
+            "\\binom"
+        } else if without_rule {
+            "\\atop"
+        } else {
+            "\\frac"
+        };
+        let mut res = TexNode::new(
+            TexNodeType::BinaryFunc,
+            fraction_command.to_string(),
+            Some(vec![numerator, denominator]),
+            None,
+        );
+        if !binomial && (left != "." || right != ".") {
+            let delimiter = |value: String| TexNode::new(TexNodeType::Element, value, None, None);
+            res = TexNode::new(
+                TexNodeType::Leftright,
+                String::new(),
+                Some(vec![delimiter(left), res, delimiter(right)]),
+                None,
+            );
+        }
+        let style_command = match style.as_str() {
+            "" => None,
+            "0" => Some("\\displaystyle"),
+            "1" => Some("\\textstyle"),
+            "2" => Some("\\scriptstyle"),
+            "3" => Some("\\scriptscriptstyle"),
+            _ => return Err(format!("Invalid style for \\genfrac: {}", style)),
+        };
+        if let Some(style_command) = style_command {
+            res = TexNode::new(TexNodeType::UnaryFunc, style_command.to_string(), Some(vec![res]), None);
+        }
+        Ok((res, pos))
+    }
+
+    // \SI[options]{9.81}{\meter} -> a node with the raw number and unit as text arguments, the options are ignored
+    fn parse_siunitx(&self, tokens: &[TexToken], start: usize) -> ParseResult {
+        let command = tokens[start].value.clone();
+        let mut pos = start + 1;
+        if pos < tokens.len() && tokens[pos].eq(&LEFT_SQUARE_BRACKET) {
+            let pos_right_square_bracket = find_closing_match(tokens, pos, &LEFT_SQUARE_BRACKET, &RIGHT_SQUARE_BRACKET);
+            if pos_right_square_bracket == -1 {
+                return Err("No matching right square bracket for [".to_string());
+            }
+            pos = pos_right_square_bracket as usize + 1;
+        }
+        let num_of_args = if matches!(command.as_str(), "\\SI" | "\\qty") {
+            2
+        } else {
+            1
+        };
+        let mut args = Vec::new();
+        for _ in 0..num_of_args {
+            let (text, new_pos) = parse_raw_text_arg(tokens, pos)?;
+            args.push(TexNode::new(TexNodeType::Text, text, None, None));
+            pos = new_pos;
+        }
+        let node_type = if num_of_args == 2 {
+            TexNodeType::BinaryFunc
+        } else {
+            TexNodeType::UnaryFunc
+        };
+        Ok((TexNode::new(node_type, command, Some(args), None), pos))
+    }
+
+    // [model]{spec} -> a text node holding the spec, with the color model as its argument if given
+    fn parse_color(&self, tokens: &[TexToken], start: usize) -> ParseResult {
+        let mut pos = start + eat_whitespaces(tokens, start);
+        let mut model = None;
+        if pos < tokens.len() && tokens[pos].eq(&LEFT_SQUARE_BRACKET) {
+            let pos_right_square_bracket = find_closing_match(tokens, pos, &LEFT_SQUARE_BRACKET, &RIGHT_SQUARE_BRACKET);
+            if pos_right_square_bracket == -1 {
+                return Err("No matching right square bracket for [".to_string());
+            }
+            let model_name = tokens[pos + 1..pos_right_square_bracket as usize]
+                .iter()
+                .map(|token| token.value.as_str())
+                .collect::<String>();
+            model = Some(vec![TexNode::new(
+                TexNodeType::Text,
+                model_name.trim().to_string(),
+                None,
+                None,
+            )]);
+            pos = pos_right_square_bracket as usize + 1;
+        }
+        let (spec, new_pos) = parse_raw_text_arg(tokens, pos)?;
+        Ok((TexNode::new(TexNodeType::Text, spec, model, None), new_pos))
+    }
+
+    fn is_declaration(&self, token: &TexToken) -> bool {
+        token.token_type == TexTokenType::Command
+            && self.command_registry.get_command_type(&token.value[1..]) == Some(CommandType::Declaration)
+    }
+
+    // \displaystyle a + b -> the declaration node with everything after it as its argument
+    // \mathversion{bold} a + b -> a binary node with the version name and everything after it
+    fn parse_declaration(&self, tokens: &[TexToken], start: usize) -> Result<TexNode, String> {
+        let command = tokens[start].value.clone();
+        if command == "\\color" {
+            let (color, pos) = self.parse_color(tokens, start + 1)?;
+            let body = self.parse(tokens[pos..].to_vec())?;
+            return Ok(TexNode::new(
+                TexNodeType::BinaryFunc,
+                command,
+                Some(vec![color, body]),
+                None,
+            ));
+        }
+        if command == "\\mathversion" {
+            let (version, pos) = parse_raw_text_arg(tokens, start + 1)?;
+            let body = self.parse(tokens[pos..].to_vec())?;
+            if body.node_type == TexNodeType::Empty {
+                return Ok(EMPTY_NODE.clone());
+            }
+            let version = TexNode::new(TexNodeType::Text, version, None, None);
+            return Ok(TexNode::new(
+                TexNodeType::BinaryFunc,
+                command,
+                Some(vec![version, body]),
+                None,
+            ));
+        }
+        let body = self.parse(tokens[start + 1..].to_vec())?;
+        // a declaration with nothing after it in its group has no effect
+        if body.node_type == TexNodeType::Empty {
+            return Ok(EMPTY_NODE.clone());
+        }
+        Ok(TexNode::new(TexNodeType::UnaryFunc, command, Some(vec![body]), None))
+    }
+
     fn parse_left_right_expr(&self, tokens: &[TexToken], start: usize) -> ParseResult {
         assert!(tokens[start].eq(&LEFT_COMMAND));
 
@@ -386,8 +729,32 @@ impl LatexParser {
         )]];
         let mut row: &mut Vec<TexNode> = &mut all_rows[0];
         let mut group: &mut TexNode = &mut row[0];
+        let mut at_cell_start = true;
 
         while pos < tokens.len() {
+            if at_cell_start {
+                // an infix fraction splits the cell it appears in, e.g. a \over b & c
+                at_cell_start = false;
+                let cell_end = find_cell_end(tokens, pos);
+                let infix_positions = find_infix_commands(&tokens[pos..cell_end]);
+                if !infix_positions.is_empty() {
+                    let res = self.parse_infix_fraction(tokens[pos..cell_end].to_vec(), &infix_positions)?;
+                    group.args.as_mut().unwrap().push(res);
+                    pos = cell_end;
+                    continue;
+                }
+            }
+            if self.is_declaration(&tokens[pos]) {
+                // inside an alignment, a declaration only lasts until the end of its cell
+                let cell_end = find_cell_end(tokens, pos);
+                group
+                    .args
+                    .as_mut()
+                    .unwrap()
+                    .push(self.parse_declaration(&tokens[..cell_end], pos)?);
+                pos = cell_end;
+                continue;
+            }
             let (res, new_pos) = self.parse_next_expr(tokens, pos)?;
             pos = new_pos;
 
@@ -409,6 +776,7 @@ impl LatexParser {
                 )]);
                 row = all_rows.last_mut().unwrap();
                 group = &mut row[0];
+                at_cell_start = true;
             } else if res.node_type == TexNodeType::Control && res.content == "&" {
                 row.push(TexNode::new(
                     TexNodeType::Ordgroup,
@@ -417,6 +785,7 @@ impl LatexParser {
                     None,
                 ));
                 group = row.last_mut().unwrap();
+                at_cell_start = true;
             } else {
                 group.args.as_mut().unwrap().push(res);
             }
@@ -426,6 +795,25 @@ impl LatexParser {
     }
 }
 
+// The braket package, whose lowercase \bra, \ket and \braket come from the physics macro pack
+fn braket_delimiters(command: &str) -> Option<(&'static str, &'static str)> {
+    match command {
+        "\\Bra" => Some(("\\langle", "|")),
+        "\\Ket" => Some(("|", "\\rangle")),
+        "\\Braket" => Some(("\\langle", "\\rangle")),
+        _ => None,
+    }
+}
+
+// Only the last of several \limits or \nolimits is effective
+fn strip_limits_modifier(node: TexNode) -> TexNode {
+    if node.node_type == TexNodeType::UnaryFunc && matches!(node.content.as_str(), "\\limits" | "\\nolimits") {
+        node.args.unwrap().remove(0)
+    } else {
+        node
+    }
+}
+
 pub fn parse_tex(tex: &str) -> Result<TexNode, String> {
     let parser = LatexParser::new(false, false);
     let tokens = tex_tokenizer::tokenize(tex)?;
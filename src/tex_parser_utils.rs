@@ -14,6 +14,11 @@ pub static LEFT_SQUARE_BRACKET: LazyLock<TexToken> =
 pub static RIGHT_SQUARE_BRACKET: LazyLock<TexToken> =
     LazyLock::new(|| TexToken::new(TexTokenType::Element, "]".to_string()));
 
+pub static LEFT_PARENTHESIS: LazyLock<TexToken> =
+    LazyLock::new(|| TexToken::new(TexTokenType::Element, "(".to_string()));
+pub static RIGHT_PARENTHESIS: LazyLock<TexToken> =
+    LazyLock::new(|| TexToken::new(TexTokenType::Element, ")".to_string()));
+
 pub fn eat_whitespaces(tokens: &[TexToken], start: usize) -> usize {
     let mut pos = start;
     while pos < tokens.len() && matches!(tokens[pos].token_type, TexTokenType::Space | TexTokenType::Newline) {
@@ -25,11 +30,15 @@ pub fn eat_whitespaces(tokens: &[TexToken], start: usize) -> usize {
 pub fn eat_parenthesis(tokens: &[TexToken], start: usize) -> Option<&TexToken> {
     let first_token = &tokens[start];
     if first_token.token_type == TexTokenType::Element
-        && ["(", ")", "[", "]", "|", "\\{", "\\}", "."].contains(&first_token.value.as_str())
+        && ["(", ")", "[", "]", "|", "\\|", "\\{", "\\}", "."].contains(&first_token.value.as_str())
     {
         Some(first_token)
     } else if first_token.token_type == TexTokenType::Command
-        && ["lfloor", "rfloor", "lceil", "rceil", "langle", "rangle"].contains(&&first_token.value[1..])
+        && [
+            "lfloor", "rfloor", "lceil", "rceil", "langle", "rangle", "vert", "lvert", "rvert", "Vert", "lVert",
+            "rVert",
+        ]
+        .contains(&&first_token.value[1..])
     {
         Some(first_token)
     } else {
@@ -85,3 +94,137 @@ pub fn find_closing_end_command(tokens: &[TexToken], start: usize) -> isize {
 
 pub static SUB_SYMBOL: LazyLock<TexToken> = LazyLock::new(|| TexToken::new(TexTokenType::Control, "_".to_string()));
 pub static SUP_SYMBOL: LazyLock<TexToken> = LazyLock::new(|| TexToken::new(TexTokenType::Control, "^".to_string()));
+
+pub static LIMITS_COMMAND: LazyLock<TexToken> =
+    LazyLock::new(|| TexToken::new(TexTokenType::Command, "\\limits".to_string()));
+pub static NOLIMITS_COMMAND: LazyLock<TexToken> =
+    LazyLock::new(|| TexToken::new(TexTokenType::Command, "\\nolimits".to_string()));
+
+// Find where the alignment cell containing `start` ends, i.e. the next & or \\ that is not nested in a group
+pub fn find_cell_end(tokens: &[TexToken], start: usize) -> usize {
+    let mut depth = 0;
+    let mut pos = start;
+    while pos < tokens.len() {
+        let token = &tokens[pos];
+        if token.eq(&LEFT_CURLY_BRACKET) || token.eq(&LEFT_COMMAND) || token.eq(&BEGIN_COMMAND) {
+            depth += 1;
+        } else if token.eq(&RIGHT_CURLY_BRACKET) || token.eq(&RIGHT_COMMAND) || token.eq(&END_COMMAND) {
+            depth -= 1;
+        } else if depth == 0
+            && token.token_type == TexTokenType::Control
+            && (token.value == "&" || token.value == "\\\\")
+        {
+            break;
+        }
+        pos += 1;
+    }
+    pos
+}
+
+// Read a braced argument such as {bold} verbatim, returning its content and the position after the closing brace
+pub fn parse_raw_text_arg(tokens: &[TexToken], start: usize) -> Result<(String, usize), String> {
+    let pos = start + eat_whitespaces(tokens, start);
+    if pos >= tokens.len() || !tokens[pos].eq(&LEFT_CURLY_BRACKET) {
+        return Err("Expecting { for the argument".to_string());
+    }
+    let pos_closing_bracket = find_closing_match(tokens, pos, &LEFT_CURLY_BRACKET, &RIGHT_CURLY_BRACKET);
+    if pos_closing_bracket == -1 {
+        return Err("Unmatched '{'".to_string());
+    }
+    let text = tokens[pos + 1..pos_closing_bracket as usize]
+        .iter()
+        .map(|token| token.value.as_str())
+        .collect::<String>();
+    Ok((text.trim().to_string(), pos_closing_bracket as usize + 1))
+}
+
+// Read a TeX length given either in braces, e.g. \hspace{1em}, or inline, e.g. \mkern-3mu or \kern 2pt
+pub fn parse_length_arg(tokens: &[TexToken], start: usize) -> Result<(String, usize), String> {
+    let mut pos = start;
+    // the starred \hspace* behaves the same in math
+    if pos < tokens.len() && tokens[pos].token_type == TexTokenType::Element && tokens[pos].value == "*" {
+        pos += 1;
+    }
+    pos += eat_whitespaces(tokens, pos);
+    if pos < tokens.len() && tokens[pos].eq(&LEFT_CURLY_BRACKET) {
+        return parse_raw_text_arg(tokens, pos);
+    }
+    // rubber lengths, e.g. \hskip\fill or \hskip\stretch{2}
+    if pos < tokens.len() && tokens[pos].token_type == TexTokenType::Command {
+        match tokens[pos].value.as_str() {
+            "\\fill" => return Ok(("\\fill".to_string(), pos + 1)),
+            "\\stretch" => {
+                let (factor, new_pos) = parse_raw_text_arg(tokens, pos + 1 + eat_whitespaces(tokens, pos + 1))?;
+                return Ok((format!("\\stretch{{{}}}", factor), new_pos));
+            }
+            _ => {}
+        }
+    }
+
+    let mut length = String::new();
+    while pos < tokens.len()
+        && tokens[pos].token_type == TexTokenType::Element
+        && (["-", "+", "."].contains(&tokens[pos].value.as_str())
+            || tokens[pos].value.starts_with(|c: char| c.is_ascii_digit()))
+    {
+        length.push_str(&tokens[pos].value);
+        pos += 1;
+    }
+    pos += eat_whitespaces(tokens, pos);
+    let unit_start = length.len();
+    while pos < tokens.len()
+        && tokens[pos].token_type == TexTokenType::Element
+        && tokens[pos].value.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        length.push_str(&tokens[pos].value);
+        pos += 1;
+    }
+    if length.len() == unit_start {
+        return Err(format!("Expecting a length but got {}", length));
+    }
+    Ok((length, pos))
+}
+
+// Plain TeX generalized fractions that split the whole group they appear in, e.g. {a \over b}
+pub const INFIX_COMMANDS: &[&str] = &["\\over", "\\atop", "\\choose", "\\brack", "\\brace"];
+
+// Find the infix fraction commands of the current group, ignoring those nested in inner groups
+pub fn find_infix_commands(tokens: &[TexToken]) -> Vec<usize> {
+    let mut depth = 0;
+    let mut positions = Vec::new();
+    for (pos, token) in tokens.iter().enumerate() {
+        if token.eq(&LEFT_CURLY_BRACKET) || token.eq(&LEFT_COMMAND) || token.eq(&BEGIN_COMMAND) {
+            depth += 1;
+        } else if token.eq(&RIGHT_CURLY_BRACKET) || token.eq(&RIGHT_COMMAND) || token.eq(&END_COMMAND) {
+            depth -= 1;
+        } else if depth == 0
+            && token.token_type == TexTokenType::Command
+            && INFIX_COMMANDS.contains(&token.value.as_str())
+        {
+            positions.push(pos);
+        }
+    }
+    positions
+}
+
+// Read a braced delimiter such as {(} or {\langle}, an empty argument stands for the null delimiter "."
+pub fn parse_delimiter_arg(tokens: &[TexToken], start: usize) -> Result<(String, usize), String> {
+    let pos = start + eat_whitespaces(tokens, start);
+    if pos >= tokens.len() || !tokens[pos].eq(&LEFT_CURLY_BRACKET) {
+        return Err("Expecting { for the delimiter".to_string());
+    }
+    let pos_closing_bracket = find_closing_match(tokens, pos, &LEFT_CURLY_BRACKET, &RIGHT_CURLY_BRACKET);
+    if pos_closing_bracket == -1 {
+        return Err("Unmatched '{'".to_string());
+    }
+    let inside: Vec<&TexToken> = tokens[pos + 1..pos_closing_bracket as usize]
+        .iter()
+        .filter(|token| !matches!(token.token_type, TexTokenType::Space | TexTokenType::Newline))
+        .collect();
+    let delimiter = match inside.as_slice() {
+        [] => ".".to_string(),
+        [token] if eat_parenthesis(std::slice::from_ref(*token), 0).is_some() => token.value.clone(),
+        _ => return Err("Invalid delimiter".to_string()),
+    };
+    Ok((delimiter, pos_closing_bracket as usize + 1))
+}
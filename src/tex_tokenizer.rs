@@ -80,12 +80,15 @@ pub fn tokenize(latex: &str) -> Result<Vec<TexToken>, String> {
                     return Err("Expecting command name after '\\'".to_string());
                 }
                 let first_two_chars = latex[pos..pos + 2].iter().collect::<String>();
-                if ["\\\\", "\\,"].contains(&&*first_two_chars) {
+                if ["\\\\", "\\,", "\\:", "\\>", "\\;", "\\!", "\\ "].contains(&&*first_two_chars) {
                     token = TexToken::new(TexTokenType::Control, first_two_chars.to_string());
                 } else if ["\\{", "\\}", "\\%", "\\$", "\\&", "\\#", "\\_", "\\|"].contains(&&*first_two_chars) {
                     token = TexToken::new(TexTokenType::Element, first_two_chars.to_string());
                 } else {
-                    let command = eat_command_name(&latex, pos + 1);
+                    let mut command = eat_command_name(&latex, pos + 1);
+                    if command == "operatorname" && latex.get(pos + 1 + command.len()) == Some(&'*') {
+                        command.push('*');
+                    }
                     token = TexToken::new(TexTokenType::Command, format!("\\{}", command));
                 }
                 pos += token.value.len();
@@ -113,7 +116,10 @@ pub fn tokenize(latex: &str) -> Result<Vec<TexToken>, String> {
         tokens.push(token.clone());
 
         if token.token_type == TexTokenType::Command
-            && matches!(token.value.as_str(), r"\text" | r"\operatorname" | r"\begin" | r"\end")
+            && matches!(
+                token.value.as_str(),
+                r"\text" | r"\fbox" | r"\operatorname" | r"\operatorname*" | r"\begin" | r"\end"
+            )
         {
             if pos >= latex.len() || latex[pos] != '{' {
                 if let Some(nn) = latex[pos..].iter().position(|&c| c == '{') {
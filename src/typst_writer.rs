@@ -1,4 +1,4 @@
-use crate::definitions::{TypstNode, TypstNodeData, TypstNodeType, TypstToken, TypstTokenType};
+use crate::definitions::{TypstNamedParams, TypstNode, TypstNodeData, TypstNodeType, TypstToken, TypstTokenType};
 use regex::Regex;
 use std::sync::LazyLock;
 
@@ -49,8 +49,9 @@ impl TypstWriter {
         no_need_space |= self.buffer.ends_with(&['(', '[', '|']) && new_str.starts_with(char::is_alphanumeric);
         // closing a clause
         no_need_space |= new_str.starts_with(&[')', '}', ']', '|']);
-        // putting the opening '(' for a function
-        no_need_space |= !self.buffer.ends_with('=') && new_str.starts_with('(');
+        // putting the opening '(' for a function, but not after a space like in "space (mod n)" from \pmod
+        no_need_space |=
+            !self.buffer.ends_with('=') && new_str.starts_with('(') && !ends_with_space_symbol(&self.buffer);
         // putting punctuation
         no_need_space |= new_str.starts_with(&['_', '^', ',', ';', '!']);
         // putting a prime
@@ -172,9 +173,14 @@ impl TypstWriter {
                     }
                 }
                 if let Some(options) = &node.options {
-                    for (key, value) in options {
+                    // named arguments follow the positional ones, separated by the same commas
+                    let has_args = node.args.as_ref().is_some_and(|args| !args.is_empty());
+                    for (i, (key, value)) in sorted_options(options).into_iter().enumerate() {
+                        if has_args || i > 0 {
+                            self.queue.push(TYPST_COMMA.clone());
+                        }
                         self.queue
-                            .push(TypstToken::new(T::Symbol, format!(", {}: {}", key, value)));
+                            .push(TypstToken::new(T::Symbol, format!("{}: {}", key, value)));
                     }
                 }
                 self.queue.push(TYPST_RIGHT_PARENTHESIS.clone());
@@ -211,7 +217,7 @@ impl TypstWriter {
                     self.inside_function_depth += 1;
                     self.queue.push(TYPST_LEFT_PARENTHESIS.clone());
                     if let Some(options) = &node.options {
-                        for (key, value) in options {
+                        for (key, value) in sorted_options(options) {
                             self.queue
                                 .push(TypstToken::new(T::Symbol, format!("{}: {}, ", key, value)));
                         }
@@ -347,6 +353,18 @@ impl TypstWriter {
     }
 }
 
+// TypstNamedParams is a HashMap, so the named arguments are sorted to be written in a stable order
+fn sorted_options(options: &TypstNamedParams) -> Vec<(&String, &String)> {
+    let mut options: Vec<_> = options.iter().collect();
+    options.sort();
+    options
+}
+
+fn ends_with_space_symbol(buffer: &str) -> bool {
+    let last_word = buffer.rsplit(' ').next().unwrap_or("");
+    ["space", "space.en", "thin", "med", "thick", "quad", "wide"].contains(&last_word) || last_word.starts_with("#h(")
+}
+
 fn is_delimiter(c: &TypstNode) -> bool {
     matches!(c.node_type, TypstNodeType::Atom)
         && ["(", ")", "[", "]", "{", "}", "|", "⌊", "⌋", "⌈", "⌉"].contains(&c.content.as_str())